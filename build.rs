@@ -4,6 +4,7 @@ use std::fs;
 use structopt::clap::{crate_name, Shell};
 use structopt::StructOpt;
 
+#[allow(dead_code)]
 #[path = "src/cli.rs"]
 mod cli;
 
@@ -1,33 +1,64 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
 use std::io::{self, Write};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process;
+use std::sync::mpsc;
 
 use derive_more::{Display, Error, From};
-use ignore::overrides::OverrideBuilder;
-use ignore::WalkBuilder;
+use git2::Repository;
+use ignore::overrides::{Override, OverrideBuilder};
+use ignore::{DirEntry, WalkBuilder, WalkState};
 use lscolors::LsColors;
 
-use crate::cli::{self, IndentationMarks};
+use crate::cli::{self, EntryType, IndentationMarks, ReportFormat, ReportMode, SortKey};
+use crate::entry::{Entry, FsEntry, WalkEntry};
+use crate::filter::{MetadataFilter, RegexFilter, TypeFilter};
+use crate::ignores::{IgnoreOptions, IgnoreStack};
 use crate::indent::{AsciiMarks, IndentationLevel, NullLevel, TreeLevel, UnicodeMarks};
-use crate::output::write_tree_item;
-use crate::pathtree::TreeBuilder;
-use crate::report::Report;
-use crate::utils::{compare_file_names, get_ls_colors};
+use crate::listing::{read_listed_trees, ListedEntry};
+use crate::output::{
+    write_content_match, write_tree_item, ErrorMarker, OutputOptions, PrintedEntry,
+};
+use crate::pathtree::{DirCounts, Tree, TreeBuilder, TreeError};
+use crate::render::write_omitted_entries;
+use crate::repo::{self, RepoEntry, RepoWalkBuilder};
+use crate::report::{Report, ReportEntry};
+use crate::search::{ContentMatch, ContentSearch};
+use crate::sort::{self, Grouping};
+use crate::top::Top;
+use crate::utils::get_ls_colors;
 
 #[derive(Debug, Display, From, Error)]
 enum Error {
+    Git(git2::Error),
     Ignore(ignore::Error),
     Io(io::Error),
+    Regex(regex::Error),
+    Tree(TreeError),
 }
 
-fn get_walk_builder(path: &Path, args: &cli::Args) -> Result<ignore::WalkBuilder, ignore::Error> {
+fn get_overrides(path: &Path, args: &cli::Args) -> Result<Option<Override>, ignore::Error> {
+    if args.patterns.is_empty() {
+        return Ok(None);
+    }
+    let mut override_builder = OverrideBuilder::new(path);
+    override_builder.case_insensitive(args.ignore_case)?;
+    for pattern in &args.patterns {
+        override_builder.add(pattern)?;
+    }
+    Ok(Some(override_builder.build()?))
+}
+
+fn get_walk_builder(path: &Path, args: &cli::Args) -> Result<WalkBuilder, ignore::Error> {
     let mut walk_builder = WalkBuilder::new(path);
     walk_builder
-        .hidden(args.print_hidden)
-        .parents(args.print_ignored)
-        .git_ignore(args.print_ignored)
-        .git_global(args.print_ignored)
-        .git_exclude(args.print_ignored)
+        .hidden(!args.print_hidden)
+        .parents(!args.print_ignored)
+        .git_ignore(!args.print_ignored)
+        .git_global(!args.print_ignored)
+        .git_exclude(!args.print_ignored)
         .follow_links(args.follow_links)
         .max_depth(args.max_depth)
         .same_file_system(args.same_file_system);
@@ -39,25 +70,323 @@ fn get_walk_builder(path: &Path, args: &cli::Args) -> Result<ignore::WalkBuilder
         walk_builder.add_custom_ignore_filename(name);
     }
 
-    if !args.patterns.is_empty() {
-        let mut override_builder = OverrideBuilder::new(path);
-        override_builder.case_insensitive(args.ignore_case)?;
-        for pattern in &args.patterns {
-            override_builder.add(pattern)?;
+    if let Some(overrides) = get_overrides(path, args)? {
+        walk_builder.overrides(overrides);
+    }
+
+    Ok(walk_builder)
+}
+
+fn get_walk(
+    path: &Path,
+    args: &cli::Args,
+    max_depth: Option<usize>,
+) -> Result<ignore::WalkParallel, ignore::Error> {
+    Ok(get_walk_builder(path, args)?
+        .max_depth(max_depth)
+        .threads(args.threads.unwrap_or(0))
+        .build_parallel())
+}
+
+/// Returns the symbolic link a walk following links failed on, if any.
+fn get_unfollowed_symlink(err: &ignore::Error) -> Option<WalkEntry> {
+    let path = get_error_path(err)?;
+    let metadata = path.symlink_metadata().ok()?;
+    if !metadata.file_type().is_symlink() {
+        return None;
+    }
+    let entry = FsEntry::new(path.to_path_buf(), Some(metadata.file_type()));
+    Some(WalkEntry::Unfollowed(entry, metadata))
+}
+
+/// Walks a directory with several threads down to `max_depth`, and returns
+/// its entries in depth-first order, sorted by path, along with the errors
+/// met on the way. The metadata of the entries is read while walking.
+/// When following links, the links that could not be followed are kept as
+/// entries rather than errors.
+fn get_direntries(
+    path: &Path,
+    args: &cli::Args,
+    max_depth: Option<usize>,
+) -> Result<(Vec<WalkEntry>, Vec<ignore::Error>), ignore::Error> {
+    let walk = get_walk(path, args, max_depth)?;
+    let (sender, receiver) = mpsc::channel();
+    walk.run(|| {
+        let sender = sender.clone();
+        Box::new(move |result: Result<DirEntry, ignore::Error>| {
+            // Read metadata here, so that it is read by the walking threads.
+            let result = result.map(|direntry| {
+                let metadata = direntry.path().symlink_metadata().ok();
+                WalkEntry::Walked(direntry, metadata)
+            });
+            sender.send(result).unwrap();
+            WalkState::Continue
+        })
+    });
+    drop(sender);
+    let (mut direntries, mut errors) = (vec![], vec![]);
+    for result in receiver {
+        match result {
+            Ok(direntry) => direntries.push(direntry),
+            Err(err) => match get_unfollowed_symlink(&err).filter(|_| args.follow_links) {
+                Some(entry) => direntries.push(entry),
+                None => errors.push(err),
+            },
         }
-        let overrides = override_builder.build()?;
+    }
+    direntries.sort_unstable_by(|direntry_1, direntry_2| direntry_1.path().cmp(direntry_2.path()));
+    Ok((direntries, errors))
+}
+
+/// Returns the path of the entry an error is about, if any.
+fn get_error_path(err: &ignore::Error) -> Option<&Path> {
+    match err {
+        ignore::Error::WithPath { path, .. } => Some(path),
+        ignore::Error::Loop { child, .. } => Some(child),
+        ignore::Error::WithDepth { err, .. } | ignore::Error::WithLineNumber { err, .. } => {
+            get_error_path(err)
+        }
+        _ => None,
+    }
+}
+
+/// Errors met while walking a directory, by path of the failing entry.
+type WalkErrors = HashMap<PathBuf, ignore::Error>;
+
+/// Counts walk errors in the report, and returns those that can be shown next
+/// to one of the entries of the printed tree, if any. The others are printed
+/// on the standard error.
+fn collect_walk_errors<T>(
+    errors: Vec<ignore::Error>,
+    tree: Option<&Tree<T>>,
+    report: &mut Report,
+) -> WalkErrors {
+    let paths = tree
+        .iter()
+        .flat_map(|tree| tree.iter())
+        .filter(|(_, node)| node.data().is_some())
+        .map(|(_, node)| node.path())
+        .collect::<HashSet<_>>();
+    let mut walk_errors = HashMap::new();
+    for err in errors {
+        report.add_error();
+        match get_error_path(&err) {
+            Some(path) if paths.contains(path) => {
+                walk_errors.insert(path.to_path_buf(), err);
+            }
+            _ => eprintln!("gitree: {}", err),
+        }
+    }
+    walk_errors
+}
+
+/// Returns whether the size of files is needed, to filter or sort entries,
+/// or to summarize them.
+fn needs_sizes(args: &cli::Args) -> bool {
+    !args.sizes.is_empty()
+        || args.sort == SortKey::Size
+        || args.types.iter().any(|t| matches!(t, EntryType::Empty))
+        || matches!(args.report_mode, ReportMode::Full)
+        || args.top.is_some()
+}
+
+fn get_repo_entries(
+    git_dir: &Path,
+    args: &cli::Args,
+    max_depth: Option<usize>,
+) -> Result<Vec<RepoEntry>, Error> {
+    let mut walk_builder = RepoWalkBuilder::new(git_dir);
+    walk_builder
+        .revision(&args.revision)
+        .hidden(!args.print_hidden)
+        .sizes(needs_sizes(args))
+        .max_depth(max_depth);
+
+    if let Some(overrides) = get_overrides(git_dir, args)? {
         walk_builder.overrides(overrides);
     }
 
-    if args.sort_files {
-        walk_builder.sort_by_file_name(&compare_file_names);
+    Ok(walk_builder.build()?)
+}
+
+/// Removes from the tree the entries left out by the filters and by
+/// `--prune`.
+fn filter_tree<E: Entry>(tree: &mut Tree<&E>, args: &cli::Args) -> Result<(), Error> {
+    let root_path = tree.root().path();
+    let exclude_filter = RegexFilter::new(&args.exclude_regexes, args.ignore_case)?;
+    if !exclude_filter.is_empty() {
+        tree.exclude(|node| exclude_filter.is_match(root_path, node.path()));
     }
+    let regex_filter = RegexFilter::new(&args.regexes, args.ignore_case)?;
+    if !regex_filter.is_empty() {
+        tree.filter(|node| regex_filter.is_match(root_path, node.path()));
+    }
+    if !args.types.is_empty() {
+        let type_filter = TypeFilter::new(&args.types);
+        tree.filter(|node| node.data().is_some_and(|e| type_filter.is_match(*e)));
+    }
+    let metadata_filter =
+        MetadataFilter::new(&args.sizes, args.changed_within, args.changed_before);
+    if !metadata_filter.is_empty() {
+        tree.filter(|node| node.data().is_some_and(|e| metadata_filter.is_match(*e)));
+    }
+    if args.prune {
+        tree.prune(|node| node.data().is_some_and(|e| e.is_dir()));
+    }
+    Ok(())
+}
 
-    Ok(walk_builder)
+fn sort_entries<E: Entry>(entries: Vec<E>, args: &cli::Args) -> Vec<E> {
+    let grouping = if args.dirs_first {
+        Grouping::DirsFirst
+    } else if args.files_first {
+        Grouping::FilesFirst
+    } else {
+        Grouping::None
+    };
+    sort::sort_entries(entries, args.sort, args.reverse, grouping, args.collation)
 }
 
-fn get_walk(path: &Path, args: &cli::Args) -> Result<ignore::Walk, ignore::Error> {
-    Ok(get_walk_builder(path, args)?.build())
+type ContentMatches = HashMap<PathBuf, ContentMatch>;
+
+type DirCountsByPath = HashMap<PathBuf, DirCounts>;
+
+/// Removes from the tree the files without a match for `--contains`, and
+/// returns the matches of the others.
+fn search_contents<E, F>(
+    tree: &mut Tree<&E>,
+    args: &cli::Args,
+    mut search_entry: F,
+) -> Result<ContentMatches, Error>
+where
+    E: Entry,
+    F: FnMut(&ContentSearch, &E) -> Option<ContentMatch>,
+{
+    let mut content_matches = HashMap::new();
+    let pattern = match args.contains {
+        Some(ref pattern) => pattern,
+        None => return Ok(content_matches),
+    };
+    let search = ContentSearch::new(pattern, args.ignore_case)?;
+    tree.filter(|node| {
+        let entry = match node.data() {
+            Some(entry) if entry.is_file() => *entry,
+            _ => return false,
+        };
+        match search_entry(&search, entry) {
+            Some(content_match) => {
+                content_matches.insert(entry.path().to_path_buf(), content_match);
+                true
+            }
+            None => false,
+        }
+    });
+    Ok(content_matches)
+}
+
+/// Counts the directories and files within each directory of the entries.
+/// Symbolic links to directories count as directories, as in the report.
+fn count_dir_entries<E: Entry>(entries: &[E]) -> Result<DirCountsByPath, TreeError> {
+    let tree = match TreeBuilder::from_entries(entries.iter().map(|e| (e.path(), e))) {
+        Ok(builder) => builder.build(),
+        Err(TreeError::Empty) => return Ok(HashMap::new()),
+        Err(err) => return Err(err),
+    };
+    Ok(tree
+        .count_entries(|node| {
+            node.data()
+                .is_some_and(|e| ReportEntry::from_entry(*e).kind.is_dir())
+        })
+        .into_iter()
+        .map(|(path, counts)| (path.to_path_buf(), counts))
+        .collect())
+}
+
+/// Returns the counts shown by `--dir-counts`, which are taken at any depth
+/// on the entries as walked, before the filters other than globs and ignore
+/// rules. Given a maximum depth, `list_all` lists the entries again without
+/// it, so that filters never see the deeper entries.
+fn get_dir_counts<E, F, G>(
+    entries: &[E],
+    args: &cli::Args,
+    list_all: F,
+) -> Result<DirCountsByPath, Error>
+where
+    E: Entry,
+    F: FnOnce() -> Result<Vec<G>, Error>,
+    G: Entry,
+{
+    if !args.dir_counts || args.report_only {
+        return Ok(HashMap::new());
+    }
+    match args.max_depth {
+        None => Ok(count_dir_entries(entries)?),
+        Some(_) => Ok(count_dir_entries(&list_all()?)?),
+    }
+}
+
+/// Returns the tree of the sorted entries.
+fn build_tree<'a, E: Entry>(entries: &'a [E]) -> Result<Tree<'a, &'a E>, TreeError> {
+    Ok(TreeBuilder::from_entries(entries.iter().map(|e| (e.path(), e)))?.build())
+}
+
+/// Returns the filtered tree as printed, after adding its entries to the
+/// report and to the top entries. With `--report-only`, the entries are
+/// added to the report as they are not printed, and no tree is returned.
+fn build_printed_tree<'a, E: Entry>(
+    mut tree: Tree<'a, &'a E>,
+    report: &mut Report,
+    top: &mut Option<Top>,
+    args: &cli::Args,
+) -> Option<Tree<'a, &'a E>> {
+    // Entries kept by the filters, before some are left out or merged.
+    let entries = tree
+        .iter()
+        .filter_map(|(_, node)| node.data().copied())
+        .collect::<Vec<_>>();
+    if args.max_entries.is_some() || args.file_limit.is_some() {
+        tree.truncate(args.max_entries, args.file_limit, |node| {
+            node.data().is_some_and(|e| e.is_dir())
+        });
+    }
+    if args.compact_dirs {
+        tree.compact_dirs();
+    }
+    if let Some(top) = top {
+        add_top_entries(top, &tree);
+    }
+    if args.report_only {
+        for (index, entry) in entries.iter().enumerate() {
+            report.add(index == 0, &ReportEntry::from_entry(*entry));
+        }
+        return None;
+    }
+    add_unprinted_entries(report, &entries, &tree);
+    Some(tree)
+}
+
+/// Adds to the report the entries without a line of their own in the tree,
+/// because they were left out of it or merged into other lines.
+fn add_unprinted_entries<E: Entry>(report: &mut Report, entries: &[&E], tree: &Tree<&E>) {
+    let printed_paths = tree
+        .iter()
+        .filter_map(|(_, node)| node.data())
+        .map(|e| e.path())
+        .collect::<HashSet<_>>();
+    for entry in entries {
+        if !printed_paths.contains(entry.path()) {
+            report.add(false, &ReportEntry::from_entry(*entry));
+        }
+    }
+}
+
+/// Adds the entries of the tree, as printed, to the top entries.
+fn add_top_entries<E: Entry>(top: &mut Top, tree: &Tree<&E>) {
+    let tree_entries = tree
+        .iter()
+        .filter_map(|(_, node)| node.data())
+        .map(|entry| (entry.path(), entry.is_dir(), entry.file_size()));
+    top.add_tree(tree_entries);
 }
 
 impl cli::ColorMode {
@@ -70,38 +399,494 @@ impl cli::ColorMode {
     }
 }
 
-fn write_tree<L, W>(output: &mut W, level: &mut L, args: &cli::Args) -> Result<(), Error>
+/// Entries of a tree, as listed by a walk, from a repository or from a list
+/// of paths.
+struct Listing<E, F> {
+    entries: Vec<E>,
+    /// Errors met while listing the entries.
+    errors: Vec<ignore::Error>,
+    /// Counts printed by `--dir-counts`, by directory.
+    dir_counts: DirCountsByPath,
+    /// Returns the match of `--contains` in the contents of a file entry.
+    search_entry: F,
+}
+
+/// Sorts, filters and prints the tree of the listed entries.
+fn write_entries_tree<E, F, L, W>(
+    output: &mut W,
+    level: &mut L,
+    report: &mut Report,
+    top: &mut Option<Top>,
+    output_options: &OutputOptions,
+    listing: Listing<E, F>,
+    args: &cli::Args,
+) -> Result<(), Error>
+where
+    E: PrintedEntry,
+    F: FnMut(&ContentSearch, &E) -> Option<ContentMatch>,
+    L: IndentationLevel,
+    W: Write,
+{
+    let Listing {
+        entries,
+        errors,
+        dir_counts,
+        search_entry,
+    } = listing;
+    let entries = sort_entries(entries, args);
+    let mut tree = build_tree(&entries)?;
+    filter_tree(&mut tree, args)?;
+    let content_matches = search_contents(&mut tree, args, search_entry)?;
+    let tree = build_printed_tree(tree, report, top, args);
+    let walk_errors = collect_walk_errors(errors, tree.as_ref(), report);
+    let tree = match tree {
+        Some(tree) => tree,
+        None => return Ok(()),
+    };
+    tree.for_each(level, &mut |level, node| {
+        if let Some(omitted) = node.omitted() {
+            return write_omitted_entries(output, level, omitted);
+        }
+        let path = node.path();
+        let entry = node.data().unwrap();
+        let error_marker = walk_errors
+            .get(path)
+            .map(|err| ErrorMarker::new(err, entry.is_dir()));
+        write_tree_item(
+            output,
+            report,
+            level,
+            *entry,
+            node.num_merged(),
+            error_marker
+                .as_ref()
+                .map(|m| m as &dyn fmt::Display)
+                .or_else(|| {
+                    entry
+                        .is_recursive_link()
+                        .then_some(&"[recursive, not followed]" as &dyn fmt::Display)
+                })
+                .or_else(|| dir_counts.get(path).map(|c| c as &dyn fmt::Display)),
+            output_options,
+        )?;
+        if let Some(content_match) = content_matches.get(path) {
+            write_content_match(output, level, content_match, &args.show_matches)?;
+        }
+        Ok::<_, io::Error>(())
+    })?;
+    Ok(())
+}
+
+fn write_dir_tree<L, W>(
+    output: &mut W,
+    level: &mut L,
+    report: &mut Report,
+    top: &mut Option<Top>,
+    output_options: &OutputOptions,
+    root_path: &Path,
+    args: &cli::Args,
+) -> Result<(), Error>
+where
+    L: IndentationLevel,
+    W: Write,
+{
+    let (entries, errors) = get_direntries(root_path, args, args.max_depth)?;
+    if entries.is_empty() {
+        // The root itself could not be read.
+        return Err(errors.into_iter().next().unwrap().into());
+    }
+    let dir_counts = get_dir_counts(&entries, args, || {
+        Ok(get_direntries(root_path, args, None)?.0)
+    })?;
+    let listing = Listing {
+        entries,
+        errors,
+        dir_counts,
+        search_entry: |search: &ContentSearch, e: &WalkEntry| search.search_file(e.path()),
+    };
+    write_entries_tree(output, level, report, top, output_options, listing, args)
+}
+
+/// Context of a tree printed while walking the directory.
+struct DirStream<'a, W> {
+    output: &'a mut W,
+    report: &'a mut Report,
+    output_options: &'a OutputOptions<'a>,
+    root_path: &'a Path,
+    ignores: IgnoreStack,
+    exclude_filter: RegexFilter,
+    args: &'a cli::Args,
+}
+
+impl<W: Write> DirStream<'_, W> {
+    fn add_errors(&mut self, errors: Vec<ignore::Error>) {
+        for err in errors {
+            self.report.add_error();
+            eprintln!("gitree: {}", err);
+        }
+    }
+
+    /// Enters a directory, and returns its sorted children and the error met
+    /// while reading it. Only the children of the directories being printed
+    /// are held in memory at any time. The directory is left at once if it
+    /// has no children to print.
+    fn list_dir(&mut self, dir_path: &Path) -> (Vec<FsEntry>, Option<ignore::Error>) {
+        let mut errors = vec![];
+        self.ignores.push(dir_path, &mut errors);
+        self.add_errors(errors);
+        let read_dir = match fs::read_dir(dir_path) {
+            Ok(read_dir) => read_dir,
+            Err(err) => {
+                self.ignores.pop();
+                self.report.add_error();
+                let err = ignore::Error::WithPath {
+                    path: dir_path.to_path_buf(),
+                    err: Box::new(ignore::Error::Io(err)),
+                };
+                return (vec![], Some(err));
+            }
+        };
+        // The directory itself comes first, as `sort_entries` expects.
+        let mut entries = vec![FsEntry::new(dir_path.to_path_buf(), None)];
+        let mut errors = vec![];
+        for result in read_dir {
+            let dir_entry = match result {
+                Ok(dir_entry) => dir_entry,
+                Err(err) => {
+                    errors.push(ignore::Error::Io(err));
+                    continue;
+                }
+            };
+            let entry = FsEntry::new(
+                dir_path.join(dir_entry.file_name()),
+                dir_entry.file_type().ok(),
+            );
+            if !self.ignores.is_ignored(entry.path(), entry.is_dir()) {
+                entries.push(entry);
+            }
+        }
+        self.add_errors(errors);
+        let mut entries = sort_entries(entries, self.args);
+        entries.remove(0);
+        if !self.exclude_filter.is_empty() {
+            entries.retain(|e| !self.exclude_filter.is_match(self.root_path, e.path()));
+        }
+        if entries.is_empty() {
+            self.ignores.pop();
+        }
+        (entries, None)
+    }
+
+    /// Prints an entry, and returns its children to print next.
+    fn write_entry<L>(
+        &mut self,
+        level: &L,
+        entry: &FsEntry,
+        depth: usize,
+    ) -> Result<Vec<FsEntry>, Error>
+    where
+        L: IndentationLevel,
+    {
+        let in_depth = self
+            .args
+            .max_depth
+            .is_none_or(|max_depth| depth < max_depth);
+        let (children, dir_error) = if entry.is_dir() && in_depth {
+            self.list_dir(entry.path())
+        } else {
+            (vec![], None)
+        };
+        let error_marker = dir_error.as_ref().map(|err| ErrorMarker::new(err, true));
+        write_tree_item(
+            self.output,
+            self.report,
+            level,
+            entry,
+            0,
+            error_marker.as_ref().map(|m| m as &dyn fmt::Display),
+            self.output_options,
+        )?;
+        Ok(children)
+    }
+
+    fn write_tree<L>(&mut self, level: &mut L, root: &FsEntry) -> Result<(), Error>
+    where
+        L: IndentationLevel,
+    {
+        // Children left to print in each directory being printed, in reverse
+        // order.
+        let mut stack: Vec<Vec<FsEntry>> = vec![];
+        let mut children = self.write_entry(level, root, 0)?;
+        loop {
+            if !children.is_empty() {
+                children.reverse();
+                level.indent();
+                stack.push(children);
+            }
+            let child = loop {
+                let dir_children = match stack.last_mut() {
+                    Some(dir_children) => dir_children,
+                    None => return Ok(()),
+                };
+                match dir_children.pop() {
+                    Some(child) => {
+                        if dir_children.is_empty() {
+                            level.set_last();
+                        }
+                        break child;
+                    }
+                    None => {
+                        stack.pop();
+                        self.ignores.pop();
+                        level.dedent();
+                    }
+                }
+            };
+            children = self.write_entry(level, &child, stack.len())?;
+        }
+    }
+}
+
+/// Prints a directory tree while walking it, one directory at a time.
+fn write_streamed_dir_tree<L, W>(
+    output: &mut W,
+    level: &mut L,
+    report: &mut Report,
+    output_options: &OutputOptions,
+    root_path: &Path,
+    args: &cli::Args,
+) -> Result<(), Error>
+where
+    L: IndentationLevel,
+    W: Write,
+{
+    let with_path = |err| ignore::Error::WithPath {
+        path: root_path.to_path_buf(),
+        err: Box::new(ignore::Error::Io(err)),
+    };
+    let root_type = root_path.metadata().map_err(with_path)?.file_type();
+    let root = FsEntry::new(root_path.to_path_buf(), Some(root_type));
+    let ignore_options = IgnoreOptions {
+        hidden: !args.print_hidden,
+        parents: !args.print_ignored,
+        ignore: true,
+        git_ignore: !args.print_ignored,
+        git_global: !args.print_ignored,
+        git_exclude: !args.print_ignored,
+    };
+    let mut errors = vec![];
+    let ignores = IgnoreStack::new(
+        root_path,
+        ignore_options,
+        &args.ignore_names,
+        &args.ignore_paths,
+        get_overrides(root_path, args)?,
+        &mut errors,
+    )
+    .map_err(with_path)?;
+    let mut stream = DirStream {
+        output,
+        report,
+        output_options,
+        root_path,
+        ignores,
+        exclude_filter: RegexFilter::new(&args.exclude_regexes, args.ignore_case)?,
+        args,
+    };
+    stream.add_errors(errors);
+    stream.write_tree(level, &root)
+}
+
+fn write_repo_tree<L, W>(
+    output: &mut W,
+    level: &mut L,
+    report: &mut Report,
+    top: &mut Option<Top>,
+    output_options: &OutputOptions,
+    git_dir: &Path,
+    args: &cli::Args,
+) -> Result<(), Error>
+where
+    L: IndentationLevel,
+    W: Write,
+{
+    let entries = get_repo_entries(git_dir, args, args.max_depth)?;
+    let dir_counts = get_dir_counts(&entries, args, || get_repo_entries(git_dir, args, None))?;
+    let repo = Repository::open_bare(git_dir)?;
+    let listing = Listing {
+        entries,
+        errors: vec![],
+        dir_counts,
+        search_entry: |search: &ContentSearch, e: &RepoEntry| {
+            repo.find_blob(e.id())
+                .ok()
+                .and_then(|blob| search.search(blob.content()))
+        },
+    };
+    write_entries_tree(output, level, report, top, output_options, listing, args)
+}
+
+/// Removes the listed entries left out by globs, along with their
+/// descendants, the way a walk skips them. The entries are sorted in
+/// depth-first order, root first.
+fn apply_globs(entries: Vec<ListedEntry>, args: &cli::Args) -> Result<Vec<ListedEntry>, Error> {
+    let overrides = match get_overrides(entries[0].path(), args)? {
+        Some(overrides) => overrides,
+        None => return Ok(entries),
+    };
+    let mut skipped_dir: Option<PathBuf> = None;
+    let mut kept_entries = vec![];
+    for (index, entry) in entries.into_iter().enumerate() {
+        if skipped_dir
+            .as_ref()
+            .is_some_and(|dir| entry.path().starts_with(dir))
+        {
+            continue;
+        }
+        let is_dir = entry.is_dir();
+        if index > 0 && overrides.matched(entry.path(), is_dir).is_ignore() {
+            if is_dir {
+                skipped_dir = Some(entry.path().to_path_buf());
+            }
+            continue;
+        }
+        kept_entries.push(entry);
+    }
+    Ok(kept_entries)
+}
+
+fn write_listed_tree<L, W>(
+    output: &mut W,
+    level: &mut L,
+    report: &mut Report,
+    top: &mut Option<Top>,
+    output_options: &OutputOptions,
+    entries: Vec<ListedEntry>,
+    args: &cli::Args,
+) -> Result<(), Error>
+where
+    L: IndentationLevel,
+    W: Write,
+{
+    let mut entries = entries;
+    for entry in &mut entries {
+        entry.read_metadata();
+    }
+    let mut entries = apply_globs(entries, args)?;
+    // Listed entries are known at any depth, and counted before being cut,
+    // after globs as walked entries are.
+    let dir_counts = if args.dir_counts && !args.report_only {
+        count_dir_entries(&entries)?
+    } else {
+        HashMap::new()
+    };
+    if let Some(max_depth) = args.max_depth {
+        let root_depth = entries[0].path().components().count();
+        entries.retain(|e| e.path().components().count() - root_depth <= max_depth);
+    }
+    let listing = Listing {
+        entries,
+        errors: vec![],
+        dir_counts,
+        search_entry: |search: &ContentSearch, e: &ListedEntry| search.search_file(e.path()),
+    };
+    write_entries_tree(output, level, report, top, output_options, listing, args)
+}
+
+/// Returns the trees of the paths listed in the file given to `--fromfile`.
+fn read_from_file(from_file: &[PathBuf]) -> io::Result<Vec<Vec<ListedEntry>>> {
+    match from_file.first() {
+        Some(path) if path != Path::new("-") => read_listed_trees(fs::File::open(path)?),
+        _ => read_listed_trees(io::stdin().lock()),
+    }
+}
+
+fn write_report<W>(output: &mut W, report: &Report, args: &cli::Args) -> io::Result<()>
+where
+    W: Write,
+{
+    let full = matches!(args.report_mode, ReportMode::Full);
+    if !args.report_only {
+        writeln!(output)?;
+    }
+    match (&args.report_format, full) {
+        (ReportFormat::Text, false) => writeln!(output, "{}", report),
+        (ReportFormat::Text, true) => writeln!(output, "{}", report.full()),
+        (ReportFormat::Json, _) => writeln!(output, "{}", report.json(full)),
+        (ReportFormat::KeyValue, _) => writeln!(output, "{}", report.key_values(full)),
+    }
+}
+
+fn write_tree<L, W>(output: &mut W, level: &mut L, args: &cli::Args) -> Result<Report, Error>
 where
     L: IndentationLevel,
     W: Write,
 {
-    let ls_colors = if args.color.use_color() {
+    let ls_colors = if args.color.use_color() && !args.report_only {
         get_ls_colors()
     } else {
         LsColors::empty()
     };
+    let output_options = OutputOptions {
+        ls_colors: &ls_colors,
+        print_path: args.print_path,
+        link_chain: args.link_chain,
+    };
     let mut report = Report::new();
-    for root_path in &args.directories {
-        let walk = get_walk(root_path, args)?;
-        let direntries = walk.collect::<Result<Vec<_>, _>>()?;
-        let tree = TreeBuilder::from_paths(&mut direntries.iter().map(|e| e.path()))
-            .unwrap()
-            .build();
-        tree.for_each(level, &mut |level, path| {
-            write_tree_item(
+    let mut top = args.top.map(Top::new);
+    if let Some(ref from_file) = args.from_file {
+        for entries in read_from_file(from_file)? {
+            write_listed_tree(
                 output,
+                level,
                 &mut report,
+                &mut top,
+                &output_options,
+                entries,
+                args,
+            )?;
+        }
+    }
+    let directories = if args.from_file.is_some() {
+        &[][..]
+    } else {
+        &args.directories[..]
+    };
+    for root_path in directories {
+        if repo::is_git_dir(root_path) {
+            write_repo_tree(
+                output,
                 level,
-                path,
-                &ls_colors,
-                args.print_path,
-            )
-        })?;
+                &mut report,
+                &mut top,
+                &output_options,
+                root_path,
+                args,
+            )?;
+        } else if args.stream {
+            write_streamed_dir_tree(output, level, &mut report, &output_options, root_path, args)?;
+        } else {
+            write_dir_tree(
+                output,
+                level,
+                &mut report,
+                &mut top,
+                &output_options,
+                root_path,
+                args,
+            )?;
+        }
+    }
+    if let Some(top) = top {
+        if !args.report_only {
+            writeln!(output)?;
+        }
+        writeln!(output, "{}", top)?;
     }
     if args.report {
-        writeln!(output, "\n{}", report)?;
+        write_report(output, &report, args)?;
     }
-    Ok(())
+    Ok(report)
 }
 
 pub fn main(args: &cli::Args) {
@@ -112,10 +897,367 @@ pub fn main(args: &cli::Args) {
         IndentationMarks::Unicode => Box::new(TreeLevel::<UnicodeMarks>::new()),
     };
     match write_tree(&mut stdout, &mut level, args) {
-        Ok(()) => process::exit(0),
+        Ok(report) if report.num_errors() == 0 => process::exit(0),
+        // Some entries could not be read.
+        Ok(_) => process::exit(1),
         Err(err) => {
             eprintln!("{}", err);
             process::exit(2);
         }
     }
 }
+
+/// Creates a directory holding the given files, with their contents, and
+/// directories, given with a trailing slash.
+#[cfg(test)]
+fn make_fixture(name: &str, files: &[(&str, &str)]) -> PathBuf {
+    let root_path = std::env::temp_dir().join(format!("gitree-{}-{}", name, process::id()));
+    let _ = fs::remove_dir_all(&root_path);
+    fs::create_dir_all(&root_path).unwrap();
+    for (path, contents) in files {
+        let path = root_path.join(path);
+        if path.as_os_str().as_encoded_bytes().ends_with(b"/") {
+            fs::create_dir_all(&path).unwrap();
+        } else {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, contents).unwrap();
+        }
+    }
+    root_path
+}
+
+/// Returns the output of `gitree` called with the given arguments.
+#[cfg(test)]
+fn run(args: &[&str]) -> String {
+    use structopt::StructOpt;
+
+    let args = cli::Args::from_iter(["gitree", "--color=never"].iter().chain(args));
+    let mut output = vec![];
+    write_tree(&mut output, &mut TreeLevel::<UnicodeMarks>::new(), &args).unwrap();
+    String::from_utf8(output).unwrap()
+}
+
+#[test]
+fn test_hidden_and_ignored_files() {
+    let root_path = make_fixture(
+        "hidden",
+        &[
+            (".git/info/exclude", "excluded.txt\n"),
+            (".gitignore", "ignored.txt\n"),
+            (".hidden", ""),
+            ("excluded.txt", ""),
+            ("ignored.txt", ""),
+            ("shown.txt", ""),
+            ("sub/.hidden", ""),
+            ("sub/ignored.txt", ""),
+            ("sub/shown.txt", ""),
+        ],
+    );
+    // The flags of `WalkBuilder` tell what to skip, the options of gitree
+    // what to print.
+    let listed = |args: &[&str], dir: &str| {
+        let path = root_path.join(dir);
+        let root = path.to_str().unwrap();
+        let output = run(&[&["-R"], args, &[root]].concat());
+        assert_eq!(output, run(&[&["-R", "--stream"], args, &[root]].concat()));
+        output
+            .lines()
+            .skip(1)
+            .map(|line| line.rsplit(' ').next().unwrap().to_owned())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(listed(&[], ""), ["shown.txt", "sub", "shown.txt"]);
+    assert_eq!(
+        listed(&["--hidden"], ""),
+        [
+            ".git",
+            "info",
+            "exclude",
+            ".gitignore",
+            ".hidden",
+            "shown.txt",
+            "sub",
+            ".hidden",
+            "shown.txt"
+        ]
+    );
+    assert_eq!(
+        listed(&["--no-ignore"], ""),
+        [
+            "excluded.txt",
+            "ignored.txt",
+            "shown.txt",
+            "sub",
+            "ignored.txt",
+            "shown.txt"
+        ]
+    );
+    // The ignore files of the parents of the root apply too.
+    assert_eq!(listed(&[], "sub"), ["shown.txt"]);
+    assert_eq!(
+        listed(&["--no-ignore"], "sub"),
+        ["ignored.txt", "shown.txt"]
+    );
+    assert_eq!(
+        listed(&["--hidden", "--no-ignore"], "sub"),
+        [".hidden", "ignored.txt", "shown.txt"]
+    );
+    fs::remove_dir_all(&root_path).unwrap();
+}
+
+#[test]
+fn test_dir_counts_with_max_depth() {
+    let root_path = make_fixture(
+        "dir-counts",
+        &[
+            ("a/x/hello.txt", "hello"),
+            ("c/y/bye.txt", "bye"),
+            ("d.txt", ""),
+        ],
+    );
+    let root = root_path.to_str().unwrap();
+    // Removes the counts following the labels.
+    let strip_counts = |output: &str| {
+        output
+            .lines()
+            .map(|line| line.split(" (").next().unwrap().to_owned())
+            .collect::<Vec<_>>()
+    };
+    let output = run(&["-R", "--prune", "-d", "1", root]);
+    assert_eq!(strip_counts(&output), [root, "└── d.txt"]);
+    let output = run(&["-R", "--prune", "-d", "1", "--dir-counts", root]);
+    assert_eq!(strip_counts(&output), [root, "└── d.txt"]);
+    assert!(output
+        .lines()
+        .next()
+        .unwrap()
+        .ends_with(" (2 directories, 1 file; 4 directories, 3 files in total)"));
+    let output = run(&["-R", "--contains", "hello", "-d", "1", root]);
+    assert_eq!(strip_counts(&output), [root]);
+    let output = run(&["-R", "--contains", "hello", "-d", "1", "--dir-counts", root]);
+    assert_eq!(strip_counts(&output), [root]);
+    fs::remove_dir_all(&root_path).unwrap();
+}
+
+#[test]
+fn test_dir_counts_as_walked() {
+    let root_path = make_fixture(
+        "dir-counts-walked",
+        &[
+            ("a/.hidden", ""),
+            ("a/b/", ""),
+            ("a/c.txt", ""),
+            ("a/d.rs", ""),
+        ],
+    );
+    std::os::unix::fs::symlink("b", root_path.join("a/e")).unwrap();
+    let root = root_path.to_str().unwrap();
+    // Returns the counts of the entries right within `a`.
+    let counts_of_a = |args: &[&str]| {
+        let output = run(&[&["--dir-counts"], args, &[root]].concat());
+        let line = output.lines().find(|line| line.contains(" a ")).unwrap();
+        let counts = line.split_once(" (").unwrap().1;
+        counts.split([';', ')']).next().unwrap().to_owned()
+    };
+    // The link to a directory counts as a directory, as in the report.
+    assert_eq!(counts_of_a(&[]), "2 directories, 2 files");
+    assert!(run(&[root]).ends_with("\n3 directories, 2 files\n"));
+    // Globs and ignore rules apply, as they decide what is walked.
+    assert_eq!(counts_of_a(&["-g", "!*.rs"]), "2 directories, 1 file");
+    assert_eq!(counts_of_a(&["--hidden"]), "2 directories, 3 files");
+    // Other filters do not.
+    assert_eq!(counts_of_a(&["--regex", "c.txt"]), "2 directories, 2 files");
+    assert_eq!(counts_of_a(&["--type", "f"]), "2 directories, 2 files");
+    fs::remove_dir_all(&root_path).unwrap();
+}
+
+#[test]
+fn test_report_of_omitted_and_merged_entries() {
+    let root_path = make_fixture(
+        "omitted",
+        &[("a/b/c/x.rs", "abc"), ("d.txt", "de"), ("e.rs", "f")],
+    );
+    let root = root_path.to_str().unwrap();
+    let report = |args: &[&str]| {
+        let output = run(&[&["--report=full", "--report-format=kv"], args, &[root]].concat());
+        let (_, report) = output.split_once("\n\n").unwrap();
+        report.to_owned()
+    };
+    let full_report = report(&[]);
+    assert!(full_report.contains("directories=3\nfiles=3\n"));
+    assert!(full_report.contains("bytes=6\n"));
+    assert_eq!(report(&["--max-entries", "1"]), full_report);
+    assert_eq!(report(&["--filelimit", "2"]), full_report);
+    assert_eq!(report(&["--compact-dirs"]), full_report);
+    fs::remove_dir_all(&root_path).unwrap();
+}
+
+#[test]
+fn test_report_only() {
+    let root_path = make_fixture(
+        "report-only",
+        &[("a/b.rs", "abc"), ("c.txt", "de"), (".hidden", "")],
+    );
+    std::os::unix::fs::symlink("a", root_path.join("link")).unwrap();
+    std::os::unix::fs::symlink("missing", root_path.join("broken")).unwrap();
+    let root = root_path.to_str().unwrap();
+    let args = ["-H", "--report=full", "--report-format=json", root];
+    let output = run(&args);
+    let (_, report) = output.split_once("\n\n").unwrap();
+    let report_only = run(&[&["--report-only"], &args[..]].concat());
+    assert_eq!(report_only, report);
+    // Nothing but the JSON object is printed, so that it can be parsed.
+    let json = report_only.strip_suffix('\n').unwrap();
+    assert!(json.starts_with("{\"directories\":2,") && json.ends_with("}}"));
+    assert!(!json.contains('\n'));
+    fs::remove_dir_all(&root_path).unwrap();
+}
+
+#[test]
+fn test_streamed_tree() {
+    let root_path = make_fixture(
+        "stream",
+        &[
+            (".git/", ""),
+            (".gitignore", "*.log\n"),
+            ("a/.ignore", "x.txt\n"),
+            ("a/b/.gitignore", "!*.log\nc.txt\n"),
+            ("a/b/c.rs", "abc"),
+            ("a/b/c.txt", ""),
+            ("a/b/d.log", ""),
+            ("a/b/x.txt", ""),
+            ("a/.e", ""),
+            ("f/x.txt", ""),
+            ("B.txt", "de"),
+            ("empty/", ""),
+            ("f/g.rs", "f"),
+        ],
+    );
+    let root = root_path.to_str().unwrap();
+    for args in [
+        &[][..],
+        &["--hidden"],
+        &["--no-ignore", "-d", "2"],
+        &["--glob", "*.rs", "--exclude-regex", "^f$"],
+        &["--sort", "size", "--reverse", "--dirs-first"],
+    ] {
+        let args = [args, &[root]].concat();
+        assert_eq!(run(&[&["--stream"], &args[..]].concat()), run(&args));
+    }
+    // A missing root is reported with its path, as without streaming.
+    let missing = root_path.join("missing");
+    let root_error = |args: &[&str]| {
+        use structopt::StructOpt;
+
+        let args = cli::Args::from_iter(["gitree"].iter().chain(args));
+        match write_tree(&mut vec![], &mut NullLevel::new(), &args) {
+            Ok(_) => panic!("{:?} was read", args.directories),
+            Err(err) => err.to_string(),
+        }
+    };
+    let missing_error = root_error(&["--stream", missing.to_str().unwrap()]);
+    assert_eq!(missing_error, root_error(&[missing.to_str().unwrap()]));
+    assert!(missing_error.contains(missing.to_str().unwrap()));
+    fs::remove_dir_all(&root_path).unwrap();
+}
+
+#[test]
+fn test_streamed_deep_tree() {
+    // The ignore files of each directory must be read once, not again for
+    // each of its descendants, which takes minutes and gigabytes at this
+    // depth.
+    const DEPTH: usize = 1000;
+    let mut paths = vec![".git/".to_owned(), ".gitignore".to_owned()];
+    let mut dir = String::new();
+    for _ in 0..DEPTH {
+        dir.push_str("d/");
+        paths.push(format!("{}x.log", dir));
+    }
+    let files = paths
+        .iter()
+        .map(|path| {
+            (
+                path.as_str(),
+                if path == ".gitignore" { "*.log\n" } else { "" },
+            )
+        })
+        .collect::<Vec<_>>();
+    let root_path = make_fixture("stream-deep", &files);
+    let root = root_path.to_str().unwrap();
+    let output = run(&["--stream", root]);
+    assert_eq!(output, run(&[root]));
+    assert!(output.ends_with(&format!("\n{} directories, 0 files\n", DEPTH)));
+    fs::remove_dir_all(&root_path).unwrap();
+}
+
+#[test]
+fn test_listed_tree_metadata() {
+    let root_path = make_fixture(
+        "listed",
+        &[
+            ("a/big.txt", "hello"),
+            ("a/empty.txt", ""),
+            ("b/", ""),
+            ("c.txt", "hi"),
+        ],
+    );
+    let root = root_path.to_str().unwrap();
+    let list_path = root_path.join("list");
+    // `b` is listed without a trailing slash, to be told a directory on disk.
+    let list = ["a/big.txt", "a/empty.txt", "b", "c.txt"]
+        .iter()
+        .map(|path| format!("{}/{}\n", root, path))
+        .collect::<String>();
+    fs::write(&list_path, list).unwrap();
+    let labels = |output: String| {
+        output
+            .lines()
+            .map(|line| line.rsplit(' ').next().unwrap().to_owned())
+            .collect::<Vec<_>>()
+    };
+    for args in [
+        &["--size", "+3"][..],
+        &["--type", "empty"],
+        &["--sort", "size", "--reverse"],
+        &["--type", "f"],
+        &["--prune"],
+        &["-g", "*.txt", "-g", "!a/"],
+    ] {
+        let common_args = [&["-R", "--exclude-regex", "^list$"], args].concat();
+        let walked = labels(run(&[&common_args[..], &[root]].concat()));
+        let fromfile_args = ["--fromfile", list_path.to_str().unwrap()];
+        let listed = labels(run(&[&common_args[..], &fromfile_args].concat()));
+        // The listed tree starts at `/`, the walked one at the fixture.
+        assert!(listed.ends_with(&walked[1..]), "{:?} {:?}", listed, walked);
+    }
+    fs::remove_dir_all(&root_path).unwrap();
+}
+
+#[test]
+fn test_repo_trees() {
+    let root_path = make_fixture("repo", &[("work/untracked.txt", "")]);
+    let bare_path = root_path.join("bare.git");
+    for repo in [
+        Repository::init(root_path.join("work")).unwrap(),
+        Repository::init_bare(&bare_path).unwrap(),
+    ] {
+        repo::commit_test_files(
+            &repo,
+            &[("a/b.txt", 0o100644, "b"), ("c.txt", 0o100644, "")],
+        );
+        repo::commit_test_files(&repo, &[("a/b.txt", 0o100644, "b"), ("d", 0o120000, "a")]);
+    }
+    // A `.git` directory prints the tree of a revision, not the work tree.
+    for git_dir in [root_path.join("work/.git"), bare_path] {
+        let git_dir = git_dir.to_str().unwrap();
+        assert_eq!(
+            run(&["-R", git_dir]),
+            format!("{}\n├── a\n│   └── b.txt\n└── d -> a\n", git_dir)
+        );
+        assert_eq!(
+            run(&["-R", "--rev", "HEAD~1", git_dir]),
+            format!("{}\n├── a\n│   └── b.txt\n└── c.txt\n", git_dir)
+        );
+    }
+    fs::remove_dir_all(&root_path).unwrap();
+}
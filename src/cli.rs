@@ -3,22 +3,18 @@ use std::fmt;
 use std::ops;
 use std::path::PathBuf;
 use std::str::FromStr;
+use std::time::SystemTime;
 
 use structopt::StructOpt;
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub enum ColorMode {
     Always,
     Never,
+    #[default]
     Auto,
 }
 
-impl Default for ColorMode {
-    fn default() -> Self {
-        ColorMode::Auto
-    }
-}
-
 impl FromStr for ColorMode {
     type Err = &'static str;
 
@@ -48,19 +44,14 @@ impl ColorMode {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub enum IndentationMarks {
     Ascii,
+    #[default]
     Unicode,
     None,
 }
 
-impl Default for IndentationMarks {
-    fn default() -> Self {
-        Self::Unicode
-    }
-}
-
 impl FromStr for IndentationMarks {
     type Err = &'static str;
 
@@ -90,6 +81,329 @@ impl IndentationMarks {
     }
 }
 
+#[derive(Debug, Default)]
+pub enum MatchAnnotation {
+    #[default]
+    None,
+    Count,
+    Line,
+}
+
+impl FromStr for MatchAnnotation {
+    type Err = &'static str;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "none" => Ok(Self::None),
+            "count" => Ok(Self::Count),
+            "line" => Ok(Self::Line),
+            _ => Err("valid values: none, count, line"),
+        }
+    }
+}
+
+impl fmt::Display for MatchAnnotation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::None => write!(f, "none"),
+            Self::Count => write!(f, "count"),
+            Self::Line => write!(f, "line"),
+        }
+    }
+}
+
+impl MatchAnnotation {
+    fn variants() -> [&'static str; 3] {
+        ["none", "count", "line"]
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SortKey {
+    #[default]
+    Name,
+    Size,
+    Mtime,
+    Ctime,
+    Extension,
+    Version,
+    None,
+}
+
+impl FromStr for SortKey {
+    type Err = &'static str;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "name" => Ok(Self::Name),
+            "size" => Ok(Self::Size),
+            "mtime" => Ok(Self::Mtime),
+            "ctime" => Ok(Self::Ctime),
+            "extension" => Ok(Self::Extension),
+            "version" => Ok(Self::Version),
+            "none" => Ok(Self::None),
+            _ => Err("valid values: name, size, mtime, ctime, extension, version, none"),
+        }
+    }
+}
+
+impl fmt::Display for SortKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Name => write!(f, "name"),
+            Self::Size => write!(f, "size"),
+            Self::Mtime => write!(f, "mtime"),
+            Self::Ctime => write!(f, "ctime"),
+            Self::Extension => write!(f, "extension"),
+            Self::Version => write!(f, "version"),
+            Self::None => write!(f, "none"),
+        }
+    }
+}
+
+impl SortKey {
+    fn variants() -> [&'static str; 7] {
+        [
+            "name",
+            "size",
+            "mtime",
+            "ctime",
+            "extension",
+            "version",
+            "none",
+        ]
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Collation {
+    #[default]
+    Ascii,
+    Unicode,
+}
+
+impl FromStr for Collation {
+    type Err = &'static str;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "ascii" => Ok(Self::Ascii),
+            "unicode" => Ok(Self::Unicode),
+            _ => Err("valid values: ascii, unicode"),
+        }
+    }
+}
+
+impl fmt::Display for Collation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Ascii => write!(f, "ascii"),
+            Self::Unicode => write!(f, "unicode"),
+        }
+    }
+}
+
+impl Collation {
+    fn variants() -> [&'static str; 2] {
+        ["ascii", "unicode"]
+    }
+}
+
+#[derive(Debug, Default)]
+pub enum ReportMode {
+    #[default]
+    Summary,
+    Full,
+}
+
+impl FromStr for ReportMode {
+    type Err = &'static str;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "summary" => Ok(Self::Summary),
+            "full" => Ok(Self::Full),
+            _ => Err("valid values: summary, full"),
+        }
+    }
+}
+
+impl fmt::Display for ReportMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Summary => write!(f, "summary"),
+            Self::Full => write!(f, "full"),
+        }
+    }
+}
+
+impl ReportMode {
+    fn variants() -> [&'static str; 2] {
+        ["summary", "full"]
+    }
+}
+
+#[derive(Debug, Default)]
+pub enum ReportFormat {
+    #[default]
+    Text,
+    Json,
+    KeyValue,
+}
+
+impl FromStr for ReportFormat {
+    type Err = &'static str;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            "kv" => Ok(Self::KeyValue),
+            _ => Err("valid values: text, json, kv"),
+        }
+    }
+}
+
+impl fmt::Display for ReportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Text => write!(f, "text"),
+            Self::Json => write!(f, "json"),
+            Self::KeyValue => write!(f, "kv"),
+        }
+    }
+}
+
+impl ReportFormat {
+    fn variants() -> [&'static str; 3] {
+        ["text", "json", "kv"]
+    }
+}
+
+#[derive(Debug)]
+pub enum EntryType {
+    Directory,
+    File,
+    Symlink,
+    Executable,
+    Empty,
+}
+
+impl FromStr for EntryType {
+    type Err = &'static str;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "d" | "directory" => Ok(Self::Directory),
+            "f" | "file" => Ok(Self::File),
+            "l" | "symlink" => Ok(Self::Symlink),
+            "x" | "executable" => Ok(Self::Executable),
+            "e" | "empty" => Ok(Self::Empty),
+            _ => Err("valid values: d, f, l, x, e"),
+        }
+    }
+}
+
+impl fmt::Display for EntryType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Directory => write!(f, "directory"),
+            Self::File => write!(f, "file"),
+            Self::Symlink => write!(f, "symlink"),
+            Self::Executable => write!(f, "executable"),
+            Self::Empty => write!(f, "empty"),
+        }
+    }
+}
+
+impl EntryType {
+    fn variants() -> [&'static str; 10] {
+        [
+            "d",
+            "directory",
+            "f",
+            "file",
+            "l",
+            "symlink",
+            "x",
+            "executable",
+            "e",
+            "empty",
+        ]
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SizeFilter {
+    Min(u64),
+    Max(u64),
+    Equals(u64),
+}
+
+impl FromStr for SizeFilter {
+    type Err = &'static str;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        const ERROR: &str = "expected a size like +1M, -10k or 512b";
+        let (constructor, value): (fn(u64) -> Self, _) = if let Some(v) = value.strip_prefix('+') {
+            (Self::Min, v)
+        } else if let Some(v) = value.strip_prefix('-') {
+            (Self::Max, v)
+        } else {
+            (Self::Equals, value)
+        };
+        let unit_start = value
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(value.len());
+        let (number, unit) = value.split_at(unit_start);
+        let number = number.parse::<u64>().map_err(|_| ERROR)?;
+        let multiplier: u64 = match unit.to_ascii_lowercase().as_str() {
+            "" | "b" => 1,
+            "k" => 1000,
+            "ki" => 1 << 10,
+            "m" => 1000 * 1000,
+            "mi" => 1 << 20,
+            "g" => 1000 * 1000 * 1000,
+            "gi" => 1 << 30,
+            "t" => 1000 * 1000 * 1000 * 1000,
+            "ti" => 1 << 40,
+            _ => return Err(ERROR),
+        };
+        let size = number.checked_mul(multiplier).ok_or(ERROR)?;
+        Ok(constructor(size))
+    }
+}
+
+/// A point in time, given either as a date or as a duration before now.
+#[derive(Clone, Copy, Debug)]
+pub struct Timestamp(SystemTime);
+
+impl Timestamp {
+    pub fn system_time(&self) -> SystemTime {
+        self.0
+    }
+}
+
+impl FromStr for Timestamp {
+    type Err = &'static str;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        const ERROR: &str = "expected a duration like 2d or a date like 2024-01-01";
+        if let Ok(duration) = humantime::parse_duration(value) {
+            return SystemTime::now()
+                .checked_sub(duration)
+                .map(Self)
+                .ok_or(ERROR);
+        }
+        let date_time = if value.len() == "YYYY-MM-DD".len() {
+            humantime::parse_rfc3339_weak(&format!("{} 00:00:00", value))
+        } else {
+            humantime::parse_rfc3339_weak(value)
+        };
+        date_time.map(Self).map_err(|_| ERROR)
+    }
+}
+
 /// Print a directory tree while respecting gitignore rules
 #[derive(Debug, StructOpt)]
 #[structopt(global_setting = structopt::clap::AppSettings::ColoredHelp)]
@@ -124,12 +438,59 @@ pub struct Args {
     /// Prints the full path of each file and directory
     #[structopt(short = "p", long = "full-path")]
     pub print_path: bool,
+    /// Prints every symbolic link of a chain of links, not only the first target
+    #[structopt(long = "link-chain")]
+    pub link_chain: bool,
     /// Maximum depth of the directory tree
     #[structopt(short = "d", long = "max-depth", value_name = "LEVEL")]
     pub max_depth: Option<usize>,
+    /// Shows the number of directories and files within each directory, at any depth, as
+    /// walked: globs and ignore rules apply, other filters do not
+    #[structopt(long = "dir-counts")]
+    pub dir_counts: bool,
+    /// Prints at most the given number of entries per directory
+    #[structopt(long = "max-entries", value_name = "NUM")]
+    pub max_entries: Option<usize>,
+    /// Does not descend into directories with more than the given number of entries
+    #[structopt(long = "filelimit", value_name = "NUM")]
+    pub file_limit: Option<usize>,
+    /// Prints the given number of largest files, largest directories and deepest paths, as
+    /// text, so not along with a report in another format
+    #[structopt(long = "top", value_name = "NUM", conflicts_with = "report-format")]
+    pub top: Option<usize>,
+    /// Merges chains of directories containing a single subdirectory into one line
+    #[structopt(long = "compact-dirs")]
+    pub compact_dirs: bool,
     /// Stays on the current filesystem only
     #[structopt(short = "x", long = "one-file-system")]
     pub same_file_system: bool,
+    /// Number of threads walking directories (defaults to the number of CPUs)
+    #[structopt(short = "j", long = "threads", value_name = "NUM")]
+    pub threads: Option<usize>,
+    /// Prints directory trees while walking them one directory at a time, with a single
+    /// thread, so that output starts at once and memory stays bounded
+    #[structopt(
+        long = "stream",
+        conflicts_with_all = &[
+            "threads",
+            "regexes",
+            "types",
+            "sizes",
+            "changed-within",
+            "changed-before",
+            "prune",
+            "contains",
+            "dir-counts",
+            "max-entries",
+            "file-limit",
+            "compact-dirs",
+            "top",
+            "follow-links",
+            "same-file-system",
+            "report-only",
+        ]
+    )]
+    pub stream: bool,
     /// Includes or excludes files and directories that match the glob pattern
     #[structopt(
         short = "g",
@@ -139,15 +500,117 @@ pub struct Args {
         number_of_values = 1
     )]
     pub patterns: Vec<String>,
-    /// Performs case-insensitive pattern matching
+    /// Includes files and directories whose name matches the regular expression
+    /// (or whose relative path matches, if the pattern contains a slash)
+    #[structopt(
+        long = "regex",
+        value_name = "PATTERN",
+        multiple = true,
+        number_of_values = 1
+    )]
+    pub regexes: Vec<String>,
+    /// Excludes files and directories whose name matches the regular expression
+    /// (or whose relative path matches, if the pattern contains a slash)
+    #[structopt(
+        long = "exclude-regex",
+        value_name = "PATTERN",
+        multiple = true,
+        number_of_values = 1
+    )]
+    pub exclude_regexes: Vec<String>,
+    /// Prints only files whose contents match the regular expression
+    #[structopt(long = "contains", value_name = "PATTERN")]
+    pub contains: Option<String>,
+    /// Shows the number of matching lines or the first matching line under files found with --contains
+    #[structopt(
+        long = "show-matches",
+        value_name = "MODE",
+        default_value = "none",
+        possible_values = &MatchAnnotation::variants()
+    )]
+    pub show_matches: MatchAnnotation,
+    /// Prints only entries of the given type, and their parent directories
+    #[structopt(
+        short = "t",
+        long = "type",
+        value_name = "TYPE",
+        multiple = true,
+        number_of_values = 1,
+        possible_values = &EntryType::variants()
+    )]
+    pub types: Vec<EntryType>,
+    /// Removes directories left without any file after filtering
+    #[structopt(long = "prune")]
+    pub prune: bool,
+    /// Prints only files whose size is at least (+), at most (-) or exactly the given size
+    #[structopt(
+        long = "size",
+        value_name = "SIZE",
+        multiple = true,
+        number_of_values = 1,
+        allow_hyphen_values = true
+    )]
+    pub sizes: Vec<SizeFilter>,
+    /// Prints only files modified within the duration (e.g. 2d) or since the date
+    #[structopt(long = "changed-within", value_name = "DATE|DURATION")]
+    pub changed_within: Option<Timestamp>,
+    /// Prints only files modified before the date or more than the duration ago
+    #[structopt(long = "changed-before", value_name = "DATE|DURATION")]
+    pub changed_before: Option<Timestamp>,
+    /// Performs case-insensitive glob and regular expression matching
     #[structopt(short = "i", long = "ignore-case")]
     pub ignore_case: bool,
-    /// Does not sort files
-    #[structopt(short = "S", long = "no-sort-files", parse(from_flag = ops::Not::not))]
-    pub sort_files: bool,
+    /// Revision to display for bare repositories and .git directories
+    #[structopt(long = "rev", value_name = "REV", default_value = "HEAD")]
+    pub revision: String,
+    /// Sorts the entries of each directory by the given key, in ascending order
+    #[structopt(
+        short = "s",
+        long = "sort",
+        value_name = "KEY",
+        default_value = "name",
+        possible_values = &SortKey::variants()
+    )]
+    pub sort: SortKey,
+    /// Reverses the sort order
+    #[structopt(short = "r", long = "reverse")]
+    pub reverse: bool,
+    /// Compares names by bytes ignoring ASCII case, or by Unicode characters ignoring case and accents
+    #[structopt(
+        long = "collation",
+        value_name = "MODE",
+        default_value = "ascii",
+        possible_values = &Collation::variants()
+    )]
+    pub collation: Collation,
+    /// Lists directories before files
+    #[structopt(long = "dirs-first", conflicts_with = "files-first")]
+    pub dirs_first: bool,
+    /// Lists files before directories
+    #[structopt(long = "files-first")]
+    pub files_first: bool,
     /// Does not print the report
     #[structopt(short = "R", long = "no-report", parse(from_flag = ops::Not::not))]
     pub report: bool,
+    /// Prints the number of directories and files only, or details and a breakdown by extension
+    #[structopt(
+        long = "report",
+        value_name = "MODE",
+        default_value = "summary",
+        possible_values = &ReportMode::variants()
+    )]
+    pub report_mode: ReportMode,
+    /// Prints the report as a sentence, a JSON object or key=value lines
+    #[structopt(
+        long = "report-format",
+        value_name = "FORMAT",
+        default_value = "text",
+        possible_values = &ReportFormat::variants()
+    )]
+    pub report_format: ReportFormat,
+    /// Prints the report without the tree
+    #[structopt(long = "report-only", conflicts_with = "report")]
+    pub report_only: bool,
     /// Uses colors for output
     #[structopt(
         short = "c",
@@ -165,6 +628,27 @@ pub struct Args {
         possible_values = &IndentationMarks::variants()
     )]
     pub indentation: IndentationMarks,
+    /// Prints the tree of the paths read from the given file, or from the standard input,
+    /// separated by newlines or NUL characters, instead of walking directories
+    #[structopt(
+        long = "fromfile",
+        value_name = "FILE",
+        multiple = false,
+        max_values = 1,
+        conflicts_with_all = &[
+            "directories",
+            "print-hidden",
+            "print-ignored",
+            "ignore-paths",
+            "ignore-names",
+            "follow-links",
+            "same-file-system",
+            "threads",
+            "stream",
+        ],
+        parse(from_os_str)
+    )]
+    pub from_file: Option<Vec<PathBuf>>,
     /// Directories to display
     #[structopt(
         value_name = "DIRECTORY",
@@ -174,3 +658,56 @@ pub struct Args {
     )]
     pub directories: Vec<PathBuf>,
 }
+
+#[test]
+fn test_parse_size_filter() {
+    assert_eq!("+1M".parse(), Ok(SizeFilter::Min(1_000_000)));
+    assert_eq!("-10k".parse(), Ok(SizeFilter::Max(10_000)));
+    assert_eq!("-10Ki".parse(), Ok(SizeFilter::Max(10_240)));
+    assert_eq!("512".parse(), Ok(SizeFilter::Equals(512)));
+    assert_eq!("512b".parse(), Ok(SizeFilter::Equals(512)));
+    assert!("+".parse::<SizeFilter>().is_err());
+    assert!("1x".parse::<SizeFilter>().is_err());
+}
+
+#[test]
+fn test_top_conflicts_with_report_format() {
+    assert!(Args::from_iter_safe(["gitree", "--top", "3", "--report-only"]).is_ok());
+    // The top entries are printed as text, which would break other formats.
+    let args = [
+        "gitree",
+        "--top",
+        "3",
+        "--report-only",
+        "--report-format",
+        "json",
+    ];
+    assert!(Args::from_iter_safe(args).is_err());
+}
+
+#[test]
+fn test_fromfile_conflicts_with_directories() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    // Listed files may have names that are not valid UTF-8.
+    let list = OsStr::from_bytes(b"list\xff");
+    let args = Args::from_iter_safe([OsStr::new("gitree"), OsStr::new("--fromfile"), list]);
+    assert_eq!(args.unwrap().from_file, Some(vec![PathBuf::from(list)]));
+    let args = Args::from_iter_safe(["gitree", "--fromfile"]).unwrap();
+    assert_eq!(args.from_file, Some(vec![]));
+    assert!(Args::from_iter_safe(["gitree", "--fromfile", "a", "--fromfile", "b"]).is_err());
+    assert!(Args::from_iter_safe(["gitree", "--fromfile", "list"]).is_ok());
+    assert!(Args::from_iter_safe(["gitree", "src", "--fromfile", "list"]).is_err());
+    for flag in [
+        "-H",
+        "-I",
+        "-L",
+        "-x",
+        "--threads=2",
+        "--ignore-path=x",
+        "--stream",
+    ] {
+        assert!(Args::from_iter_safe(["gitree", flag, "--fromfile", "list"]).is_err());
+    }
+}
@@ -0,0 +1,256 @@
+use std::fs;
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use ignore::DirEntry;
+
+use crate::listing::ListedEntry;
+use crate::repo::{RepoEntry, RepoEntryKind};
+
+/// An entry listed by a directory or repository walk.
+pub trait Entry {
+    fn path(&self) -> &Path;
+
+    fn is_dir(&self) -> bool;
+
+    fn is_file(&self) -> bool;
+
+    fn is_symlink(&self) -> bool;
+
+    /// Returns the metadata of the entry, or of its target if it is a
+    /// symbolic link followed by the walk, if it can be read.
+    fn metadata(&self) -> Option<fs::Metadata>;
+
+    fn is_executable(&self) -> bool {
+        self.metadata()
+            .is_some_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+    }
+
+    /// Returns whether the entry is an empty file or directory.
+    fn is_empty(&self) -> bool {
+        if self.is_dir() {
+            fs::read_dir(self.path()).is_ok_and(|mut d| d.next().is_none())
+        } else if self.is_file() {
+            self.metadata().is_some_and(|m| m.len() == 0)
+        } else {
+            false
+        }
+    }
+
+    /// Returns the size of the entry, if it is a file.
+    fn file_size(&self) -> Option<u64> {
+        self.metadata().filter(|m| m.is_file()).map(|m| m.len())
+    }
+
+    /// Returns the last modification time of the entry, if known.
+    fn modified(&self) -> Option<SystemTime> {
+        self.metadata()?.modified().ok()
+    }
+
+    /// Returns the last status change time of the entry, if known.
+    fn changed(&self) -> Option<SystemTime> {
+        let metadata = self.metadata()?;
+        let duration = Duration::new(
+            u64::try_from(metadata.ctime()).ok()?,
+            u32::try_from(metadata.ctime_nsec()).ok()?,
+        );
+        UNIX_EPOCH.checked_add(duration)
+    }
+}
+
+impl Entry for DirEntry {
+    #[inline]
+    fn path(&self) -> &Path {
+        self.path()
+    }
+
+    fn is_dir(&self) -> bool {
+        self.file_type().is_some_and(|t| t.is_dir())
+    }
+
+    fn is_file(&self) -> bool {
+        self.file_type().is_some_and(|t| t.is_file())
+    }
+
+    fn is_symlink(&self) -> bool {
+        self.file_type().is_some_and(|t| t.is_symlink())
+    }
+
+    #[inline]
+    fn metadata(&self) -> Option<fs::Metadata> {
+        DirEntry::metadata(self).ok()
+    }
+}
+
+/// An entry listed by `fs::read_dir`.
+pub struct FsEntry {
+    path: PathBuf,
+    file_type: Option<fs::FileType>,
+}
+
+impl FsEntry {
+    pub fn new(path: PathBuf, file_type: Option<fs::FileType>) -> Self {
+        FsEntry { path, file_type }
+    }
+}
+
+impl Entry for FsEntry {
+    #[inline]
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn is_dir(&self) -> bool {
+        self.file_type.is_some_and(|t| t.is_dir())
+    }
+
+    fn is_file(&self) -> bool {
+        self.file_type.is_some_and(|t| t.is_file())
+    }
+
+    fn is_symlink(&self) -> bool {
+        self.file_type.is_some_and(|t| t.is_symlink())
+    }
+
+    #[inline]
+    fn metadata(&self) -> Option<fs::Metadata> {
+        self.path.symlink_metadata().ok()
+    }
+}
+
+/// An entry listed by a directory walk, or a symbolic link the walk could
+/// not follow, because it is broken or leads back to one of its parents,
+/// with its metadata as read by the walk without following symbolic links.
+pub enum WalkEntry {
+    Walked(DirEntry, Option<fs::Metadata>),
+    Unfollowed(FsEntry, fs::Metadata),
+}
+
+impl WalkEntry {
+    pub fn is_unfollowed(&self) -> bool {
+        matches!(self, WalkEntry::Unfollowed(..))
+    }
+
+    /// Returns the metadata of the entry itself, if read by the walk.
+    pub fn symlink_metadata(&self) -> Option<&fs::Metadata> {
+        match self {
+            WalkEntry::Walked(_, metadata) => metadata.as_ref(),
+            WalkEntry::Unfollowed(_, metadata) => Some(metadata),
+        }
+    }
+}
+
+impl Entry for WalkEntry {
+    #[inline]
+    fn path(&self) -> &Path {
+        match self {
+            WalkEntry::Walked(e, _) => Entry::path(e),
+            WalkEntry::Unfollowed(e, _) => e.path(),
+        }
+    }
+
+    fn is_dir(&self) -> bool {
+        match self {
+            WalkEntry::Walked(e, _) => Entry::is_dir(e),
+            WalkEntry::Unfollowed(e, _) => e.is_dir(),
+        }
+    }
+
+    fn is_file(&self) -> bool {
+        match self {
+            WalkEntry::Walked(e, _) => Entry::is_file(e),
+            WalkEntry::Unfollowed(e, _) => e.is_file(),
+        }
+    }
+
+    fn is_symlink(&self) -> bool {
+        match self {
+            WalkEntry::Walked(e, _) => Entry::is_symlink(e),
+            WalkEntry::Unfollowed(e, _) => e.is_symlink(),
+        }
+    }
+
+    fn metadata(&self) -> Option<fs::Metadata> {
+        match self {
+            WalkEntry::Walked(entry, Some(metadata))
+                if entry.file_type() == Some(metadata.file_type()) =>
+            {
+                Some(metadata.clone())
+            }
+            WalkEntry::Walked(entry, _) => entry.metadata().ok(),
+            WalkEntry::Unfollowed(_, metadata) => Some(metadata.clone()),
+        }
+    }
+}
+
+impl Entry for RepoEntry {
+    #[inline]
+    fn path(&self) -> &Path {
+        self.path()
+    }
+
+    #[inline]
+    fn is_dir(&self) -> bool {
+        self.kind().is_dir()
+    }
+
+    #[inline]
+    fn is_file(&self) -> bool {
+        matches!(self.kind(), RepoEntryKind::Blob | RepoEntryKind::Executable)
+    }
+
+    #[inline]
+    fn is_symlink(&self) -> bool {
+        self.kind() == RepoEntryKind::Link
+    }
+
+    #[inline]
+    fn metadata(&self) -> Option<fs::Metadata> {
+        // Entries of a revision are not on disk, and Git does not record
+        // modification times.
+        None
+    }
+
+    #[inline]
+    fn is_executable(&self) -> bool {
+        self.kind() == RepoEntryKind::Executable
+    }
+
+    #[inline]
+    fn is_empty(&self) -> bool {
+        // Git does not store empty trees.
+        self.is_file() && self.size() == Some(0)
+    }
+
+    #[inline]
+    fn file_size(&self) -> Option<u64> {
+        self.size().filter(|_| self.is_file())
+    }
+}
+
+impl Entry for ListedEntry {
+    #[inline]
+    fn path(&self) -> &Path {
+        self.path()
+    }
+
+    fn is_dir(&self) -> bool {
+        self.is_listed_dir() || self.symlink_metadata().is_some_and(|m| m.is_dir())
+    }
+
+    fn is_file(&self) -> bool {
+        // Without metadata, entries other than directories are files.
+        !Entry::is_dir(self) && self.symlink_metadata().is_none_or(|m| m.is_file())
+    }
+
+    fn is_symlink(&self) -> bool {
+        self.symlink_metadata()
+            .is_some_and(|m| m.file_type().is_symlink())
+    }
+
+    #[inline]
+    fn metadata(&self) -> Option<fs::Metadata> {
+        self.symlink_metadata().cloned()
+    }
+}
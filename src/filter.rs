@@ -0,0 +1,152 @@
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::time::SystemTime;
+
+use regex::bytes::{Regex, RegexBuilder};
+
+use crate::cli::{EntryType, SizeFilter, Timestamp};
+use crate::entry::Entry;
+
+/// Matches entries against the file types given with `--type`.
+pub struct TypeFilter {
+    directories: bool,
+    files: bool,
+    symlinks: bool,
+    executables_only: bool,
+    empty_only: bool,
+}
+
+impl TypeFilter {
+    pub fn new(types: &[EntryType]) -> Self {
+        let mut filter = TypeFilter {
+            directories: false,
+            files: false,
+            symlinks: false,
+            executables_only: false,
+            empty_only: false,
+        };
+        for entry_type in types {
+            match entry_type {
+                EntryType::Directory => filter.directories = true,
+                EntryType::File => filter.files = true,
+                EntryType::Symlink => filter.symlinks = true,
+                EntryType::Executable => {
+                    filter.executables_only = true;
+                    filter.files = true;
+                }
+                EntryType::Empty => filter.empty_only = true,
+            }
+        }
+        if !(filter.directories || filter.files || filter.symlinks) {
+            filter.directories = true;
+            filter.files = true;
+        }
+        filter
+    }
+
+    pub fn is_match<E: Entry>(&self, entry: &E) -> bool {
+        (self.directories && entry.is_dir()
+            || self.files && entry.is_file()
+            || self.symlinks && entry.is_symlink())
+            && (!self.executables_only || entry.is_executable())
+            && (!self.empty_only || entry.is_empty())
+    }
+}
+
+impl SizeFilter {
+    pub fn is_match(self, size: u64) -> bool {
+        match self {
+            Self::Min(min) => size >= min,
+            Self::Max(max) => size <= max,
+            Self::Equals(value) => size == value,
+        }
+    }
+}
+
+/// Matches files against size and modification time limits.
+pub struct MetadataFilter {
+    sizes: Vec<SizeFilter>,
+    changed_within: Option<SystemTime>,
+    changed_before: Option<SystemTime>,
+}
+
+impl MetadataFilter {
+    pub fn new(
+        sizes: &[SizeFilter],
+        changed_within: Option<Timestamp>,
+        changed_before: Option<Timestamp>,
+    ) -> Self {
+        MetadataFilter {
+            sizes: sizes.to_vec(),
+            changed_within: changed_within.map(|t| t.system_time()),
+            changed_before: changed_before.map(|t| t.system_time()),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sizes.is_empty() && self.changed_within.is_none() && self.changed_before.is_none()
+    }
+
+    pub fn is_match<E: Entry>(&self, entry: &E) -> bool {
+        if !entry.is_file() {
+            return false;
+        }
+        if !self.sizes.is_empty() {
+            match entry.file_size() {
+                Some(size) if self.sizes.iter().all(|s| s.is_match(size)) => {}
+                _ => return false,
+            }
+        }
+        if self.changed_within.is_some() || self.changed_before.is_some() {
+            let modified = match entry.modified() {
+                Some(modified) => modified,
+                None => return false,
+            };
+            if self.changed_within.is_some_and(|limit| modified < limit)
+                || self.changed_before.is_some_and(|limit| modified > limit)
+            {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// Matches entries against regular expressions. Patterns containing a slash
+/// are matched against the path relative to the root directory, other
+/// patterns against the file name.
+pub struct RegexFilter {
+    regexes: Vec<(Regex, bool)>,
+}
+
+impl RegexFilter {
+    pub fn new(patterns: &[String], case_insensitive: bool) -> Result<Self, regex::Error> {
+        let regexes = patterns
+            .iter()
+            .map(|pattern| {
+                let regex = RegexBuilder::new(pattern)
+                    .case_insensitive(case_insensitive)
+                    .build()?;
+                Ok((regex, pattern.contains('/')))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(RegexFilter { regexes })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.regexes.is_empty()
+    }
+
+    /// Returns whether any of the regular expressions matches the path.
+    pub fn is_match(&self, root_path: &Path, path: &Path) -> bool {
+        let relative_path = path.strip_prefix(root_path).unwrap_or(path);
+        let file_name = path.file_name().unwrap_or(path.as_os_str());
+        self.regexes.iter().any(|(regex, full_path)| {
+            if *full_path {
+                regex.is_match(relative_path.as_os_str().as_bytes())
+            } else {
+                regex.is_match(file_name.as_bytes())
+            }
+        })
+    }
+}
@@ -0,0 +1,263 @@
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, BufRead};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::overrides::Override;
+use ignore::Match;
+
+/// Which ignore rules apply, as set on `ignore::WalkBuilder`.
+#[derive(Clone, Copy, Debug)]
+pub struct IgnoreOptions {
+    pub hidden: bool,
+    pub parents: bool,
+    pub ignore: bool,
+    pub git_ignore: bool,
+    pub git_global: bool,
+    pub git_exclude: bool,
+}
+
+/// Ignore rules read from a directory.
+struct DirIgnores {
+    custom: Gitignore,
+    ignore: Gitignore,
+    git_ignore: Gitignore,
+    git_exclude: Gitignore,
+    has_git: bool,
+}
+
+/// Returns the matcher for the ignore files of the given names, adding the
+/// errors met while reading them.
+fn read_ignore_files<S: AsRef<Path>>(
+    dir: &Path,
+    ignore_file_dir: &Path,
+    names: &[S],
+    errors: &mut Vec<ignore::Error>,
+) -> Gitignore {
+    let mut builder = GitignoreBuilder::new(dir);
+    for name in names {
+        let path = ignore_file_dir.join(name);
+        if path.exists() {
+            if let Some(err) = builder.add(path) {
+                errors.push(err);
+            }
+        }
+    }
+    builder.build().unwrap_or_else(|err| {
+        errors.push(err);
+        Gitignore::empty()
+    })
+}
+
+/// Returns the directory holding `info/exclude` for the repository whose
+/// `.git` is in `dir`, following the `gitdir:` line of worktrees.
+fn get_git_common_dir(dir: &Path) -> io::Result<PathBuf> {
+    let git_path = dir.join(".git");
+    if !git_path.is_file() {
+        return Ok(git_path);
+    }
+    let mut line = String::new();
+    io::BufReader::new(fs::File::open(&git_path)?).read_line(&mut line)?;
+    let git_dir = match line.trim_end().strip_prefix("gitdir: ") {
+        Some(git_dir) => PathBuf::from(git_dir),
+        None => return Ok(git_path),
+    };
+    let mut line = String::new();
+    match fs::File::open(git_dir.join("commondir")) {
+        Ok(file) => io::BufReader::new(file).read_line(&mut line)?,
+        Err(_) => return Ok(git_dir),
+    };
+    Ok(git_dir.join(line.trim_end()))
+}
+
+/// The ignore rules of the directories from the root of a walk down to the
+/// directory being listed, applied the way `ignore::Walk` applies them. The
+/// rules of a directory are pushed when entering it and popped when leaving
+/// it, so that each ignore file is read once, whatever the depth.
+pub struct IgnoreStack {
+    options: IgnoreOptions,
+    custom_names: Vec<OsString>,
+    overrides: Option<Override>,
+    explicit: Vec<Gitignore>,
+    global: Gitignore,
+    /// Ignore rules of the parents of the root, deepest first.
+    parents: Vec<DirIgnores>,
+    root_path: PathBuf,
+    /// Absolute path of the root.
+    absolute_root: PathBuf,
+    /// Ignore rules of the directories entered, from the root down.
+    dirs: Vec<DirIgnores>,
+}
+
+impl IgnoreStack {
+    pub fn new(
+        root_path: &Path,
+        options: IgnoreOptions,
+        custom_names: &[OsString],
+        ignore_paths: &[PathBuf],
+        overrides: Option<Override>,
+        errors: &mut Vec<ignore::Error>,
+    ) -> io::Result<Self> {
+        let root_path = root_path.strip_prefix("./").unwrap_or(root_path);
+        let absolute_root = root_path.canonicalize()?;
+        let explicit = ignore_paths
+            .iter()
+            .map(|path| read_ignore_files(Path::new(""), Path::new(""), &[path], errors))
+            .collect();
+        let global = if options.git_global {
+            let (global, err) = Gitignore::global();
+            errors.extend(err);
+            global
+        } else {
+            Gitignore::empty()
+        };
+        let mut stack = IgnoreStack {
+            options,
+            custom_names: custom_names.to_vec(),
+            overrides,
+            explicit,
+            global,
+            parents: vec![],
+            root_path: root_path.to_path_buf(),
+            absolute_root,
+            dirs: vec![],
+        };
+        if options.parents || options.git_ignore || options.git_exclude || options.git_global {
+            let parents = stack
+                .absolute_root
+                .ancestors()
+                .skip(1)
+                .map(|parent| stack.read_dir_ignores(parent, errors))
+                .collect();
+            stack.parents = parents;
+        }
+        Ok(stack)
+    }
+
+    fn read_dir_ignores(&self, dir: &Path, errors: &mut Vec<ignore::Error>) -> DirIgnores {
+        let options = self.options;
+        let has_git = (options.git_ignore || options.git_exclude) && dir.join(".git").exists();
+        let custom = read_ignore_files(dir, dir, &self.custom_names, errors);
+        let ignore = if options.ignore {
+            read_ignore_files(dir, dir, &[".ignore"], errors)
+        } else {
+            Gitignore::empty()
+        };
+        let git_ignore = if options.git_ignore {
+            read_ignore_files(dir, dir, &[".gitignore"], errors)
+        } else {
+            Gitignore::empty()
+        };
+        let git_exclude = if options.git_exclude && has_git {
+            match get_git_common_dir(dir) {
+                Ok(git_dir) => read_ignore_files(dir, &git_dir, &["info/exclude"], errors),
+                Err(err) => {
+                    errors.push(ignore::Error::WithPath {
+                        path: dir.join(".git"),
+                        err: Box::new(ignore::Error::Io(err)),
+                    });
+                    Gitignore::empty()
+                }
+            }
+        } else {
+            Gitignore::empty()
+        };
+        DirIgnores {
+            custom,
+            ignore,
+            git_ignore,
+            git_exclude,
+            has_git,
+        }
+    }
+
+    /// Reads the ignore rules of a directory being entered, a child of the
+    /// last directory entered.
+    pub fn push(&mut self, dir: &Path, errors: &mut Vec<ignore::Error>) {
+        let dir = dir.strip_prefix("./").unwrap_or(dir);
+        let dir_ignores = self.read_dir_ignores(dir, errors);
+        self.dirs.push(dir_ignores);
+    }
+
+    /// Drops the ignore rules of the last directory entered, when leaving it.
+    pub fn pop(&mut self) {
+        self.dirs.pop();
+    }
+
+    /// Returns whether the entry is skipped by the walk.
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let path = path.strip_prefix("./").unwrap_or(path);
+        if let Some(ref overrides) = self.overrides {
+            match overrides.matched(path, is_dir) {
+                Match::None => {}
+                Match::Ignore(_) => return true,
+                Match::Whitelist(_) => return false,
+            }
+        }
+        match self.matched(path, is_dir) {
+            Match::Ignore(()) => true,
+            Match::Whitelist(()) => false,
+            Match::None => {
+                self.options.hidden
+                    && path
+                        .file_name()
+                        .is_some_and(|name| name.as_bytes().starts_with(b"."))
+            }
+        }
+    }
+
+    fn matched(&self, path: &Path, is_dir: bool) -> Match<()> {
+        let options = self.options;
+        let any_git = self.dirs.iter().any(|d| d.has_git) || self.parents.iter().any(|d| d.has_git);
+        let (mut m_custom, mut m_ignore, mut m_git_ignore, mut m_git_exclude) =
+            (Match::None, Match::None, Match::None, Match::None);
+        let mut saw_git = false;
+        let mut match_dir = |dir_ignores: &DirIgnores, path: &Path| {
+            if m_custom.is_none() {
+                m_custom = dir_ignores.custom.matched(path, is_dir).map(|_| ());
+            }
+            if m_ignore.is_none() {
+                m_ignore = dir_ignores.ignore.matched(path, is_dir).map(|_| ());
+            }
+            if any_git && !saw_git {
+                if m_git_ignore.is_none() {
+                    m_git_ignore = dir_ignores.git_ignore.matched(path, is_dir).map(|_| ());
+                }
+                if m_git_exclude.is_none() {
+                    m_git_exclude = dir_ignores.git_exclude.matched(path, is_dir).map(|_| ());
+                }
+            }
+            saw_git = saw_git || dir_ignores.has_git;
+        };
+        for dir_ignores in self.dirs.iter().rev() {
+            match_dir(dir_ignores, path);
+        }
+        if options.parents {
+            let relative_path = path.strip_prefix(&self.root_path).unwrap_or(path);
+            let absolute_path = self.absolute_root.join(relative_path);
+            for dir_ignores in &self.parents {
+                match_dir(dir_ignores, &absolute_path);
+            }
+        }
+        let m_global = if any_git {
+            self.global.matched(path, is_dir).map(|_| ())
+        } else {
+            Match::None
+        };
+        let m_explicit = self
+            .explicit
+            .iter()
+            .rev()
+            .map(|gitignore| gitignore.matched(path, is_dir).map(|_| ()))
+            .find(|m| !m.is_none())
+            .unwrap_or(Match::None);
+        m_custom
+            .or(m_ignore)
+            .or(m_git_ignore)
+            .or(m_git_exclude)
+            .or(m_global)
+            .or(m_explicit)
+    }
+}
@@ -1,14 +1,22 @@
+//! Indentation of the lines of a tree.
+
 use std::fmt;
 use std::marker::PhantomData;
 use std::ops::DerefMut;
 
+/// Strings drawing the branches of a tree.
 pub trait IndentationMarks {
+    /// Indentation below the last child of a directory.
     const TAB: &'static str;
+    /// Indentation below a child of a directory that is not the last one.
     const BAR: &'static str;
+    /// Branch to a child of a directory that is not the last one.
     const TEE: &'static str;
+    /// Branch to the last child of a directory.
     const ELL: &'static str;
 }
 
+/// Branches drawn with ASCII characters, such as `|--`.
 pub struct AsciiMarks;
 
 impl IndentationMarks for AsciiMarks {
@@ -18,6 +26,7 @@ impl IndentationMarks for AsciiMarks {
     const ELL: &'static str = "`-- ";
 }
 
+/// Branches drawn with box-drawing characters, such as `├──`.
 pub struct UnicodeMarks;
 
 impl IndentationMarks for UnicodeMarks {
@@ -27,16 +36,46 @@ impl IndentationMarks for UnicodeMarks {
     const ELL: &'static str = "└── ";
 }
 
+/// Indentation of the current line of a tree, displayed before its label.
 pub trait IndentationLevel: fmt::Display {
+    /// Moves to the first child of the current node.
     fn indent(&mut self);
 
+    /// Moves back to the parent of the current node.
     fn dedent(&mut self);
 
+    /// Marks the current node as the last child of its parent.
     fn set_last(&mut self);
 
+    /// Returns whether the current node is the root.
     fn is_empty(&self) -> bool;
+
+    /// Formats the indentation of extra lines following the current item.
+    fn fmt_continuation(&self, f: &mut fmt::Formatter) -> fmt::Result;
+
+    /// Returns the indentation of extra lines following the current item,
+    /// for use with `write!`.
+    fn continuation(&self) -> Continuation<'_, Self>
+    where
+        Self: Sized,
+    {
+        Continuation(self)
+    }
+}
+
+/// Displays the indentation of extra lines following the current item.
+pub struct Continuation<'a, L: ?Sized>(&'a L);
+
+impl<L> fmt::Display for Continuation<'_, L>
+where
+    L: IndentationLevel + ?Sized,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt_continuation(f)
+    }
 }
 
+/// Indentation drawing the branches of the tree with the marks `M`.
 pub struct TreeLevel<M> {
     items: Vec<bool>,
     phantom: PhantomData<M>,
@@ -49,10 +88,13 @@ impl<M> Default for TreeLevel<M> {
 }
 
 impl<M> TreeLevel<M> {
+    /// Returns the indentation of the root.
     pub fn new() -> Self {
         Self::default()
     }
 
+    /// Returns the indentation of the root, with room for `capacity` levels
+    /// before reallocating.
     pub fn with_capacity(capacity: usize) -> Self {
         TreeLevel {
             items: Vec::with_capacity(capacity),
@@ -99,8 +141,16 @@ where
     fn is_empty(&self) -> bool {
         self.items.is_empty()
     }
+
+    fn fmt_continuation(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for item in &self.items {
+            write!(f, "{}", if *item { M::TAB } else { M::BAR })?;
+        }
+        Ok(())
+    }
 }
 
+/// No indentation at all.
 pub struct NullLevel {
     empty: bool,
 }
@@ -112,6 +162,7 @@ impl Default for NullLevel {
 }
 
 impl NullLevel {
+    /// Returns the indentation of the root.
     pub fn new() -> Self {
         Self::default()
     }
@@ -139,6 +190,11 @@ impl IndentationLevel for NullLevel {
     fn is_empty(&self) -> bool {
         self.empty
     }
+
+    #[inline]
+    fn fmt_continuation(&self, _f: &mut fmt::Formatter) -> fmt::Result {
+        Ok(())
+    }
 }
 
 impl<T: DerefMut<Target = dyn IndentationLevel> + fmt::Display> IndentationLevel for T {
@@ -161,4 +217,9 @@ impl<T: DerefMut<Target = dyn IndentationLevel> + fmt::Display> IndentationLevel
     fn is_empty(&self) -> bool {
         self.deref().is_empty()
     }
+
+    #[inline]
+    fn fmt_continuation(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.deref().fmt_continuation(f)
+    }
 }
@@ -0,0 +1,36 @@
+//! Building and printing trees of paths, as done by the `gitree` command.
+//!
+//! A [`pathtree::Tree`] is built by a [`pathtree::TreeBuilder`] from any
+//! iterator of paths, sorted so that each path comes right after its parent
+//! directory or its previous sibling with their descendants. It can then be
+//! written by [`render::write_tree`] to any writer, indented by one of the
+//! [`indent::IndentationLevel`] implementations. Before that, it can be
+//! transformed with [`pathtree::Tree::filter`], [`pathtree::Tree::exclude`],
+//! [`pathtree::Tree::prune`], [`pathtree::Tree::map`] and
+//! [`pathtree::Tree::fold`], and other formats can be produced by a
+//! [`pathtree::TreeVisitor`].
+//!
+//! ```
+//! use std::path::Path;
+//!
+//! use gitree::indent::{TreeLevel, UnicodeMarks};
+//! use gitree::pathtree::TreeBuilder;
+//! use gitree::render::write_tree;
+//!
+//! let paths = ["src", "src/bin", "src/bin/main.rs", "src/lib.rs"];
+//! let tree = TreeBuilder::from_paths(paths.iter().map(Path::new))
+//!     .unwrap()
+//!     .build();
+//! let mut output = vec![];
+//! write_tree(&mut output, &tree, &mut TreeLevel::<UnicodeMarks>::new()).unwrap();
+//! assert_eq!(
+//!     String::from_utf8(output).unwrap(),
+//!     "src\n├── bin\n│   └── main.rs\n└── lib.rs\n",
+//! );
+//! ```
+
+#![warn(missing_docs)]
+
+pub mod indent;
+pub mod pathtree;
+pub mod render;
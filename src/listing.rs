@@ -0,0 +1,144 @@
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::fs;
+use std::io::{self, Read};
+use std::iter;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Component, Path, PathBuf};
+
+/// An entry read from a list of paths, looked up on disk once its metadata
+/// is read.
+pub struct ListedEntry {
+    path: PathBuf,
+    is_dir: bool,
+    metadata: Option<fs::Metadata>,
+}
+
+impl ListedEntry {
+    #[inline]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns whether the entry was listed with a trailing slash, or is the
+    /// parent directory of another entry.
+    #[inline]
+    pub fn is_listed_dir(&self) -> bool {
+        self.is_dir
+    }
+
+    /// Returns the metadata of the entry itself, if read and found on disk.
+    #[inline]
+    pub fn symlink_metadata(&self) -> Option<&fs::Metadata> {
+        self.metadata.as_ref()
+    }
+
+    /// Reads the metadata of the entry, without following symbolic links.
+    pub fn read_metadata(&mut self) {
+        self.metadata = self.path.symlink_metadata().ok();
+    }
+}
+
+/// Returns the path a listed path stands for, with `.` and `..` components
+/// resolved lexically, without looking up symbolic links. Relative paths are
+/// put under `.`, unless they lead out of it.
+fn normalize_listed_path(listed_path: &Path) -> PathBuf {
+    let mut components = vec![];
+    for component in listed_path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match components.last() {
+                Some(Component::Normal(_)) => {
+                    components.pop();
+                }
+                // The parent directory of the root is the root.
+                Some(Component::RootDir) => {}
+                _ => components.push(component),
+            },
+            _ => components.push(component),
+        }
+    }
+    match components.first() {
+        Some(Component::RootDir | Component::ParentDir) => components.into_iter().collect(),
+        _ => iter::once(Component::CurDir).chain(components).collect(),
+    }
+}
+
+/// Reads paths separated by NUL characters, or by newlines if there is none,
+/// and returns their trees with the missing parent directories added.
+/// Relative paths are put under `.`, or under `..` if they lead out of it,
+/// and absolute paths under `/`. Entries of each tree are sorted in
+/// depth-first order, root first.
+pub fn read_listed_trees<R: Read>(mut reader: R) -> io::Result<Vec<Vec<ListedEntry>>> {
+    let mut contents = vec![];
+    reader.read_to_end(&mut contents)?;
+    let separator = if contents.contains(&b'\0') {
+        b'\0'
+    } else {
+        b'\n'
+    };
+    let mut paths = BTreeMap::new();
+    for line in contents.split(|byte| *byte == separator) {
+        if line.is_empty() {
+            continue;
+        }
+        let path = normalize_listed_path(Path::new(OsStr::from_bytes(line)));
+        for ancestor in path.ancestors().skip(1) {
+            if ancestor.as_os_str().is_empty() {
+                break;
+            }
+            paths.insert(ancestor.to_path_buf(), true);
+        }
+        *paths.entry(path).or_default() |= line.ends_with(b"/");
+    }
+    let mut trees: Vec<Vec<ListedEntry>> = vec![];
+    for (path, is_dir) in paths {
+        let entry = ListedEntry {
+            path,
+            is_dir,
+            metadata: None,
+        };
+        match trees.last_mut() {
+            Some(entries) if entry.path.starts_with(&entries[0].path) => entries.push(entry),
+            _ => trees.push(vec![entry]),
+        }
+    }
+    Ok(trees)
+}
+
+#[test]
+fn test_read_listed_trees() {
+    let contents =
+        b"src/b.rs\0/etc/hosts\0./src/a/\0README\0src.txt\0src/c/../d\0/../tmp\0../x/y\0a/../..\0";
+    let trees = read_listed_trees(&contents[..]).unwrap();
+    let entries = trees
+        .iter()
+        .map(|entries| {
+            entries
+                .iter()
+                .map(|e| (e.path().to_str().unwrap(), e.is_listed_dir()))
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        entries,
+        [
+            vec![
+                ("/", true),
+                ("/etc", true),
+                ("/etc/hosts", false),
+                ("/tmp", false),
+            ],
+            vec![
+                (".", true),
+                ("./README", false),
+                ("./src", true),
+                ("./src/a", true),
+                ("./src/b.rs", false),
+                ("./src/d", false),
+                ("./src.txt", false),
+            ],
+            vec![("..", true), ("../x", true), ("../x/y", false)],
+        ]
+    );
+}
@@ -1,11 +1,19 @@
 use structopt::StructOpt;
 
+use gitree::{indent, pathtree, render};
+
 mod app;
 mod cli;
-mod indent;
+mod entry;
+mod filter;
+mod ignores;
+mod listing;
 mod output;
-mod pathtree;
+mod repo;
 mod report;
+mod search;
+mod sort;
+mod top;
 mod utils;
 
 fn main() {
@@ -1,34 +1,93 @@
-use std::borrow;
-use std::ffi::OsStr;
+use std::collections::HashSet;
+use std::fmt;
 use std::fs;
 use std::io::{self, Write};
-use std::path::Path;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
 
-use lscolors::{LsColors, Style};
+use lscolors::{Indicator, LsColors, Style};
 
+use crate::cli::MatchAnnotation;
+use crate::entry::{Entry, FsEntry, WalkEntry};
 use crate::indent::IndentationLevel;
-use crate::report::Report;
+use crate::listing::ListedEntry;
+use crate::render::{path_label, pluralize};
+use crate::repo::RepoEntry;
+use crate::report::{Report, ReportEntry, ReportKind};
+use crate::search::ContentMatch;
 
-fn get_path_label(path: &Path, print_path: bool) -> borrow::Cow<str> {
-    if print_path {
-        path.to_string_lossy()
-    } else {
-        path.file_name()
-            .map(OsStr::to_string_lossy)
-            .unwrap_or_else(|| "..".into())
+/// Settings shared by all the lines of a tree.
+pub struct OutputOptions<'a> {
+    pub ls_colors: &'a LsColors,
+    pub print_path: bool,
+    pub link_chain: bool,
+}
+
+/// How the line of an entry is written.
+pub enum LineKind<'a> {
+    /// An entry on disk, looked up unless its metadata, read without
+    /// following symbolic links, is given.
+    Disk(Option<&'a fs::Metadata>),
+    /// An entry not found on disk, such as an entry of a Git revision,
+    /// styled after its type, with the target of symbolic links.
+    Virtual(Option<&'a Path>),
+}
+
+/// An entry printed on a line of a tree.
+pub trait PrintedEntry: Entry {
+    /// Returns how the line of the entry is written.
+    fn line_kind(&self) -> LineKind<'_>;
+
+    /// Returns whether the entry is a symbolic link to a directory that was
+    /// not followed, because it leads back to one of its parents.
+    fn is_recursive_link(&self) -> bool {
+        false
+    }
+}
+
+impl PrintedEntry for WalkEntry {
+    fn line_kind(&self) -> LineKind<'_> {
+        LineKind::Disk(self.symlink_metadata())
+    }
+
+    fn is_recursive_link(&self) -> bool {
+        self.is_unfollowed() && self.path().is_dir()
+    }
+}
+
+impl PrintedEntry for FsEntry {
+    fn line_kind(&self) -> LineKind<'_> {
+        LineKind::Disk(None)
+    }
+}
+
+impl PrintedEntry for RepoEntry {
+    fn line_kind(&self) -> LineKind<'_> {
+        LineKind::Virtual(self.target())
+    }
+}
+
+impl PrintedEntry for ListedEntry {
+    fn line_kind(&self) -> LineKind<'_> {
+        match self.symlink_metadata() {
+            Some(metadata) => LineKind::Disk(Some(metadata)),
+            // Listed paths need not be on disk.
+            None => LineKind::Virtual(None),
+        }
     }
 }
 
 fn write_path_label<W>(
     output: &mut W,
     path: &Path,
+    num_merged: usize,
     style: Option<&ansi_term::Style>,
     print_path: bool,
 ) -> io::Result<()>
 where
     W: Write,
 {
-    let label = get_path_label(path, print_path);
+    let label = path_label(path, num_merged, print_path);
     if let Some(style) = style {
         write!(output, "{}", style.paint(label))?;
     } else {
@@ -37,74 +96,335 @@ where
     Ok(())
 }
 
-fn write_file_line<'a, W>(
-    toplevel: bool,
+/// Maximum number of symbolic links followed to resolve a chain, as on Linux.
+const MAX_LINKS: usize = 40;
+
+/// Final target of a symbolic link.
+enum LinkTarget {
+    Found(fs::Metadata),
+    Broken,
+    Loop,
+}
+
+/// Returns the targets of a symbolic link and of the symbolic links it leads
+/// to, as written in each link, along with its final target. Relative
+/// targets are resolved from the directory of the link.
+fn resolve_symlink(path: &Path) -> io::Result<(Vec<PathBuf>, LinkTarget)> {
+    let mut targets = vec![];
+    let mut seen = HashSet::new();
+    let mut link_path = path.to_path_buf();
+    loop {
+        if !seen.insert(link_path.clone()) || targets.len() >= MAX_LINKS {
+            return Ok((targets, LinkTarget::Loop));
+        }
+        let target = fs::read_link(&link_path)?;
+        let target_path = match link_path.parent() {
+            Some(parent) => parent.join(&target),
+            None => target.clone(),
+        };
+        targets.push(target);
+        match target_path.symlink_metadata() {
+            Ok(metadata) if metadata.file_type().is_symlink() => link_path = target_path,
+            _ => break,
+        }
+    }
+    match fs::metadata(path) {
+        Ok(metadata) => Ok((targets, LinkTarget::Found(metadata))),
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok((targets, LinkTarget::Broken)),
+        Err(err) => Err(err),
+    }
+}
+
+/// Writes the path of an entry on disk, with the targets of symbolic links,
+/// and returns its kind for the report.
+fn write_disk_entry<W>(
     output: &mut W,
     report: &mut Report,
     path: &Path,
-    ls_colors: &'a LsColors,
+    metadata: &fs::Metadata,
+    num_merged: usize,
     print_path: bool,
-) -> io::Result<()>
+    options: &OutputOptions,
+) -> io::Result<ReportKind>
 where
     W: Write,
 {
-    let metadata = path.symlink_metadata()?;
+    let ls_colors = options.ls_colors;
     let style = ls_colors
-        .style_for_path_with_metadata(path, Some(&metadata))
+        .style_for_path_with_metadata(path, Some(metadata))
         .map(Style::to_ansi_term_style);
-    write_path_label(output, path, style.as_ref(), print_path)?;
+    write_path_label(output, path, num_merged, style.as_ref(), print_path)?;
     let file_type = metadata.file_type();
-    if file_type.is_symlink() {
+    if file_type.is_dir() {
+        return Ok(ReportKind::Dir);
+    } else if file_type.is_file() {
+        return Ok(ReportKind::File);
+    } else if !file_type.is_symlink() {
+        return Ok(ReportKind::Special);
+    }
+    let (targets, link_target) = match resolve_symlink(path) {
+        Ok(resolved) => resolved,
+        Err(err) => {
+            write!(output, " [error: {}]", err)?;
+            report.add_error();
+            return Ok(ReportKind::BrokenSymlink);
+        }
+    };
+    let num_targets = if options.link_chain { targets.len() } else { 1 };
+    let (last_target, first_targets) = targets[..num_targets].split_last().unwrap();
+    let link_style = ls_colors
+        .style_for_indicator(Indicator::SymbolicLink)
+        .map(Style::to_ansi_term_style);
+    for target in first_targets {
         write!(output, " -> ")?;
-        let relative_target = fs::read_link(path)?;
-        match fs::canonicalize(&relative_target) {
-            Ok(absolute_target) => {
-                let target_metadata = absolute_target.symlink_metadata()?;
-                let target_style = ls_colors
-                    .style_for_path_with_metadata(path, Some(&target_metadata))
-                    .map(Style::to_ansi_term_style);
-                write_path_label(
-                    output,
-                    relative_target.as_path(),
-                    target_style.as_ref(),
-                    true,
-                )?;
-                report.add(toplevel, target_metadata.file_type());
-            }
-            Err(ref err) if err.kind() == io::ErrorKind::NotFound => {
-                write_path_label(output, relative_target.as_path(), style.as_ref(), true)?;
-                report.add(toplevel, file_type);
-            }
-            Err(err) => return Err(err),
-        };
+        write_path_label(output, target, 0, link_style.as_ref(), true)?;
+    }
+    write!(output, " -> ")?;
+    match link_target {
+        LinkTarget::Found(target_metadata) => {
+            let target_style = ls_colors
+                .style_for_path_with_metadata(path, Some(&target_metadata))
+                .map(Style::to_ansi_term_style);
+            write_path_label(output, last_target, 0, target_style.as_ref(), true)?;
+            Ok(ReportKind::Symlink {
+                target_is_dir: target_metadata.is_dir(),
+            })
+        }
+        LinkTarget::Broken => {
+            write_path_label(output, last_target, 0, style.as_ref(), true)?;
+            write!(output, " [broken]")?;
+            Ok(ReportKind::BrokenSymlink)
+        }
+        LinkTarget::Loop => {
+            write_path_label(output, last_target, 0, style.as_ref(), true)?;
+            write!(output, " [loop]")?;
+            Ok(ReportKind::BrokenSymlink)
+        }
+    }
+}
+
+/// Writes the path of an entry not on disk, styled after its type, with the
+/// target of symbolic links.
+fn write_virtual_entry<W, E>(
+    output: &mut W,
+    entry: &E,
+    link_target: Option<&Path>,
+    num_merged: usize,
+    print_path: bool,
+    options: &OutputOptions,
+) -> io::Result<()>
+where
+    W: Write,
+    E: Entry,
+{
+    let ls_colors = options.ls_colors;
+    let path = entry.path();
+    let style = if entry.is_dir() {
+        ls_colors.style_for_indicator(Indicator::Directory)
+    } else if entry.is_symlink() {
+        ls_colors.style_for_indicator(Indicator::SymbolicLink)
+    } else if entry.is_executable() {
+        ls_colors.style_for_indicator(Indicator::ExecutableFile)
     } else {
-        report.add(toplevel, file_type);
+        ls_colors.style_for_path_with_metadata(path, None)
+    }
+    .map(Style::to_ansi_term_style);
+    write_path_label(output, path, num_merged, style.as_ref(), print_path)?;
+    if let Some(target) = link_target {
+        write!(output, " -> ")?;
+        let target_style = ls_colors
+            .style_for_path_with_metadata(target, None)
+            .map(Style::to_ansi_term_style);
+        write_path_label(output, target, 0, target_style.as_ref(), true)?;
     }
-    writeln!(output)?;
     Ok(())
 }
 
-pub fn write_tree_item<'a, L, W>(
+pub fn write_tree_item<L, W, E>(
     output: &mut W,
     report: &mut Report,
     level: &L,
-    path: &Path,
-    ls_colors: &'a LsColors,
-    print_path: bool,
+    entry: &E,
+    num_merged: usize,
+    annotation: Option<&dyn fmt::Display>,
+    options: &OutputOptions,
 ) -> io::Result<()>
 where
     L: IndentationLevel,
     W: Write,
+    E: PrintedEntry,
 {
     write!(output, "{}", level)?;
     let toplevel = level.is_empty();
-    write_file_line(
-        toplevel,
-        output,
-        report,
-        path,
-        ls_colors,
-        toplevel || print_path,
-    )?;
+    let print_path = toplevel || options.print_path;
+    let path = entry.path();
+    match entry.line_kind() {
+        LineKind::Disk(metadata) => {
+            let metadata = match metadata.map_or_else(|| path.symlink_metadata(), |m| Ok(m.clone()))
+            {
+                Ok(metadata) => metadata,
+                Err(err) => {
+                    write_path_label(output, path, num_merged, None, print_path)?;
+                    writeln!(output, " [error: {}]", err)?;
+                    report.add_error();
+                    return Ok(());
+                }
+            };
+            let kind = write_disk_entry(
+                output, report, path, &metadata, num_merged, print_path, options,
+            )?;
+            let file_type = metadata.file_type();
+            report.add(
+                toplevel,
+                &ReportEntry {
+                    path,
+                    kind,
+                    executable: file_type.is_file() && metadata.permissions().mode() & 0o111 != 0,
+                    size: if file_type.is_file() {
+                        metadata.len()
+                    } else {
+                        0
+                    },
+                },
+            );
+        }
+        LineKind::Virtual(link_target) => {
+            write_virtual_entry(output, entry, link_target, num_merged, print_path, options)?;
+            report.add(toplevel, &ReportEntry::from_entry(entry));
+        }
+    }
+    if let Some(annotation) = annotation {
+        write!(output, " {}", annotation)?;
+    }
+    writeln!(output)?;
+    Ok(())
+}
+
+pub fn write_content_match<L, W>(
+    output: &mut W,
+    level: &L,
+    content_match: &ContentMatch,
+    annotation: &MatchAnnotation,
+) -> io::Result<()>
+where
+    L: IndentationLevel,
+    W: Write,
+{
+    match annotation {
+        MatchAnnotation::None => {}
+        MatchAnnotation::Count => {
+            let count = content_match.count();
+            writeln!(
+                output,
+                "{}  {} {}",
+                level.continuation(),
+                count,
+                pluralize(count, "match", "matches")
+            )?;
+        }
+        MatchAnnotation::Line => {
+            writeln!(
+                output,
+                "{}  {}: {}",
+                level.continuation(),
+                content_match.first_line_number(),
+                content_match.first_line()
+            )?;
+        }
+    }
     Ok(())
 }
+
+/// Marker printed next to an entry that could not be read.
+pub struct ErrorMarker<'a> {
+    error: &'a ignore::Error,
+    is_dir: bool,
+}
+
+impl<'a> ErrorMarker<'a> {
+    pub fn new(error: &'a ignore::Error, is_dir: bool) -> Self {
+        ErrorMarker { error, is_dir }
+    }
+}
+
+impl fmt::Display for ErrorMarker<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_dir {
+            write!(f, "[error opening dir]")
+        } else if let Some(err) = self.error.io_error() {
+            write!(f, "[error: {}]", err)
+        } else {
+            write!(f, "[error: {}]", self.error)
+        }
+    }
+}
+
+#[test]
+fn test_resolve_symlink() {
+    use std::os::unix::fs::symlink;
+
+    use crate::indent::{TreeLevel, UnicodeMarks};
+
+    let root_path = std::env::temp_dir().join(format!("gitree-links-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&root_path);
+    fs::create_dir_all(root_path.join("sub")).unwrap();
+    fs::write(root_path.join("sub/target.txt"), "").unwrap();
+    for (link, target) in [
+        // Relative targets are resolved from the directory of the link.
+        ("sub/rel", "target.txt"),
+        ("hop1", "hop2"),
+        ("hop2", "sub/rel"),
+        ("broken", "missing"),
+        ("loop1", "loop2"),
+        ("loop2", "loop1"),
+    ] {
+        symlink(target, root_path.join(link)).unwrap();
+    }
+    let resolve = |link: &str| {
+        let (targets, link_target) = resolve_symlink(&root_path.join(link)).unwrap();
+        let targets = targets
+            .iter()
+            .map(|target| target.to_str().unwrap().to_owned())
+            .collect::<Vec<_>>();
+        let marker = match link_target {
+            LinkTarget::Found(metadata) if metadata.is_file() => "file",
+            LinkTarget::Found(_) => "other",
+            LinkTarget::Broken => "broken",
+            LinkTarget::Loop => "loop",
+        };
+        (targets, marker)
+    };
+    assert_eq!(resolve("sub/rel"), (vec!["target.txt".to_owned()], "file"));
+    assert_eq!(resolve("hop1").0, ["hop2", "sub/rel", "target.txt"]);
+    assert_eq!(resolve("hop1").1, "file");
+    assert_eq!(resolve("broken"), (vec!["missing".to_owned()], "broken"));
+    assert_eq!(resolve("loop1").0, ["loop2", "loop1"]);
+    assert_eq!(resolve("loop1").1, "loop");
+
+    let ls_colors = LsColors::empty();
+    let line = |link: &str, link_chain: bool| {
+        let options = OutputOptions {
+            ls_colors: &ls_colors,
+            print_path: false,
+            link_chain,
+        };
+        let mut level = TreeLevel::<UnicodeMarks>::new();
+        level.indent();
+        level.set_last();
+        let entry = FsEntry::new(root_path.join(link), None);
+        let mut output = vec![];
+        let mut report = Report::new();
+        write_tree_item(&mut output, &mut report, &level, &entry, 0, None, &options).unwrap();
+        String::from_utf8(output).unwrap()
+    };
+    assert_eq!(line("sub/rel", false), "└── rel -> target.txt\n");
+    assert_eq!(line("hop1", false), "└── hop1 -> hop2\n");
+    assert_eq!(
+        line("hop1", true),
+        "└── hop1 -> hop2 -> sub/rel -> target.txt\n"
+    );
+    assert_eq!(line("broken", true), "└── broken -> missing [broken]\n");
+    assert_eq!(line("loop1", false), "└── loop1 -> loop2 [loop]\n");
+    assert_eq!(line("loop1", true), "└── loop1 -> loop2 -> loop1 [loop]\n");
+    fs::remove_dir_all(&root_path).unwrap();
+}
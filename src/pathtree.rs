@@ -1,75 +1,644 @@
-use std::path::Path;
+//! Trees of paths.
+
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+use std::iter;
+use std::path::{Path, PathBuf};
 
 use crate::indent::IndentationLevel;
 
+/// Index of a node within a tree.
 pub type TreeIndex = usize;
 
-pub struct TreeNode<'a> {
+/// Number of directories and files left out of a tree by `Tree::truncate`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct OmittedEntries {
+    num_dirs: usize,
+    num_files: usize,
+}
+
+impl OmittedEntries {
+    /// Returns the number of directories left out.
+    #[inline]
+    pub fn num_dirs(&self) -> usize {
+        self.num_dirs
+    }
+
+    /// Returns the number of files left out.
+    #[inline]
+    pub fn num_files(&self) -> usize {
+        self.num_files
+    }
+}
+
+/// Number of directories and files within a directory.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DirCounts {
+    num_dirs: usize,
+    num_files: usize,
+    total_dirs: usize,
+    total_files: usize,
+}
+
+impl DirCounts {
+    /// Returns the number of direct subdirectories.
+    #[inline]
+    pub fn num_dirs(&self) -> usize {
+        self.num_dirs
+    }
+
+    /// Returns the number of files directly in the directory.
+    #[inline]
+    pub fn num_files(&self) -> usize {
+        self.num_files
+    }
+
+    /// Returns the number of subdirectories, recursively.
+    #[inline]
+    pub fn total_dirs(&self) -> usize {
+        self.total_dirs
+    }
+
+    /// Returns the number of files, recursively.
+    #[inline]
+    pub fn total_files(&self) -> usize {
+        self.total_files
+    }
+}
+
+/// Node of a tree, standing for a path or for entries left out of a
+/// directory. Nodes built from the given paths hold the payload `T` that
+/// came with their path.
+pub struct TreeNode<'a, T = ()> {
     path: &'a Path,
+    data: Option<T>,
     children: Vec<TreeIndex>,
+    num_merged: usize,
+    omitted: Option<OmittedEntries>,
 }
 
-impl<'a> TreeNode<'a> {
-    fn new(path: &'a Path, children: Vec<TreeIndex>) -> Self {
-        TreeNode { path, children }
+impl<'a, T> TreeNode<'a, T> {
+    fn new(path: &'a Path, data: Option<T>, children: Vec<TreeIndex>) -> Self {
+        TreeNode {
+            path,
+            data,
+            children,
+            num_merged: 0,
+            omitted: None,
+        }
+    }
+
+    /// Returns the path of the node, or the path of the parent directory if
+    /// the node stands for omitted entries.
+    #[inline]
+    pub fn path(&self) -> &'a Path {
+        self.path
+    }
+
+    /// Returns the payload of the node, unless it stands for a parent
+    /// directory missing from the given paths, or for omitted entries.
+    #[inline]
+    pub fn data(&self) -> Option<&T> {
+        self.data.as_ref()
+    }
+
+    /// Returns the indices of the children of the node.
+    #[inline]
+    pub fn children(&self) -> &[TreeIndex] {
+        &self.children
+    }
+
+    /// Returns the number of parent directories merged into this node by
+    /// `Tree::compact_dirs`.
+    #[inline]
+    pub fn num_merged(&self) -> usize {
+        self.num_merged
+    }
+
+    /// Returns the entries left out of the parent directory, if this node
+    /// stands for them rather than for a path.
+    #[inline]
+    pub fn omitted(&self) -> Option<OmittedEntries> {
+        self.omitted
     }
 }
 
-pub struct Tree<'a> {
-    nodes: Vec<TreeNode<'a>>,
+/// Tree of paths borrowed from the iterator it was built from.
+pub struct Tree<'a, T = ()> {
+    nodes: Vec<TreeNode<'a, T>>,
 }
 
-impl<'a> Tree<'a> {
+impl<'a, T> Tree<'a, T> {
     const ROOT: TreeIndex = 0;
 
+    /// Returns the root node of the tree.
+    #[inline]
+    pub fn root(&self) -> &TreeNode<'a, T> {
+        self.get_node(Self::ROOT)
+    }
+
+    /// Returns the node at `index`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is not the index of a node of the tree.
     #[inline]
-    pub fn get_node(&self, index: TreeIndex) -> &TreeNode<'a> {
+    pub fn get_node(&self, index: TreeIndex) -> &TreeNode<'a, T> {
         &self.nodes[index]
     }
 
-    fn _for_each<E, F, L>(&self, func: &mut F, level: &mut L, node: &TreeNode) -> Result<(), E>
+    /// Returns the indices of the nodes of the tree in depth-first order.
+    /// Children are visited in reverse order, which does not matter to
+    /// bottom-up computations.
+    fn indices(&self) -> Vec<TreeIndex> {
+        let mut indices = Vec::with_capacity(self.nodes.len());
+        let mut stack = vec![Self::ROOT];
+        while let Some(index) = stack.pop() {
+            indices.push(index);
+            stack.extend(&self.nodes[index].children);
+        }
+        indices
+    }
+
+    /// Counts the directories and files within each directory of the tree.
+    /// Nodes without children are directories if `is_dir` says so.
+    pub fn count_entries<P>(&self, is_dir: P) -> HashMap<&'a Path, DirCounts>
     where
-        F: FnMut(&L, &Path) -> Result<(), E>,
-        L: IndentationLevel,
+        P: Fn(&TreeNode<'a, T>) -> bool,
     {
-        func(level, node.path)?;
-        if let Some((last_index, first_indices)) = node.children.split_last() {
-            level.indent();
-            for child_index in first_indices {
-                let child_node = &self.get_node(*child_index);
-                self._for_each(func, level, child_node)?;
+        let counts = self.fold(|node, children: &[&(bool, DirCounts)]| {
+            let mut node_counts = DirCounts::default();
+            for (child_is_dir, child_counts) in children {
+                if *child_is_dir {
+                    node_counts.num_dirs += 1;
+                } else {
+                    node_counts.num_files += 1;
+                }
+                node_counts.total_dirs += child_counts.total_dirs;
+                node_counts.total_files += child_counts.total_files;
+            }
+            node_counts.total_dirs += node_counts.num_dirs;
+            node_counts.total_files += node_counts.num_files;
+            (!node.children.is_empty() || is_dir(node), node_counts)
+        });
+        counts
+            .iter()
+            .filter_map(|(_, node)| match node.data() {
+                Some((true, node_counts)) => Some((node.path, *node_counts)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Computes a value for each node from the node and the values of its
+    /// children, bottom-up, and returns the tree holding these values.
+    pub fn fold<U, F>(&self, mut func: F) -> Tree<'a, U>
+    where
+        F: FnMut(&TreeNode<'a, T>, &[&U]) -> U,
+    {
+        let mut values = iter::repeat_with(|| None)
+            .take(self.nodes.len())
+            .collect::<Vec<_>>();
+        for index in self.indices().into_iter().rev() {
+            let node = &self.nodes[index];
+            let child_values = node
+                .children
+                .iter()
+                .map(|child_index| values[*child_index].as_ref().unwrap())
+                .collect::<Vec<_>>();
+            let value = func(node, &child_values);
+            values[index] = Some(value);
+        }
+        let nodes = self
+            .nodes
+            .iter()
+            .zip(values)
+            .map(|(node, value)| TreeNode {
+                path: node.path,
+                data: value,
+                children: node.children.clone(),
+                num_merged: node.num_merged,
+                omitted: node.omitted,
+            })
+            .collect();
+        Tree { nodes }
+    }
+
+    /// Returns the tree with the payload of each node mapped by `func`.
+    pub fn map<U, F>(self, mut func: F) -> Tree<'a, U>
+    where
+        F: FnMut(T) -> U,
+    {
+        let nodes = self
+            .nodes
+            .into_iter()
+            .map(|node| TreeNode {
+                path: node.path,
+                data: node.data.map(&mut func),
+                children: node.children,
+                num_merged: node.num_merged,
+                omitted: node.omitted,
+            })
+            .collect();
+        Tree { nodes }
+    }
+
+    /// Removes the nodes that do not match `predicate`, unless one of their
+    /// descendants does. The root node is kept.
+    pub fn filter<P>(&mut self, mut predicate: P)
+    where
+        P: FnMut(&TreeNode<'a, T>) -> bool,
+    {
+        let mut kept = vec![false; self.nodes.len()];
+        for index in self.indices().into_iter().rev() {
+            let node = &mut self.nodes[index];
+            node.children.retain(|child_index| kept[*child_index]);
+            kept[index] = !node.children.is_empty() || predicate(node);
+        }
+    }
+
+    /// Removes the nodes that match `predicate`, along with their
+    /// descendants, which are not matched. The root node is kept.
+    pub fn exclude<P>(&mut self, mut predicate: P)
+    where
+        P: FnMut(&TreeNode<'a, T>) -> bool,
+    {
+        let mut stack = vec![Self::ROOT];
+        while let Some(index) = stack.pop() {
+            let mut children = std::mem::take(&mut self.nodes[index].children);
+            children.retain(|child_index| !predicate(&self.nodes[*child_index]));
+            stack.extend(&children);
+            self.nodes[index].children = children;
+        }
+    }
+
+    /// Removes the directories left without children, recursively. The root
+    /// node is kept. Nodes without children are directories if `is_dir` says
+    /// so.
+    pub fn prune<P>(&mut self, is_dir: P)
+    where
+        P: Fn(&TreeNode<'a, T>) -> bool,
+    {
+        let mut kept = vec![false; self.nodes.len()];
+        for index in self.indices().into_iter().rev() {
+            let node = &mut self.nodes[index];
+            let is_dir_node = !node.children.is_empty() || is_dir(node);
+            node.children.retain(|child_index| kept[*child_index]);
+            kept[index] = !node.children.is_empty() || !is_dir_node;
+        }
+    }
+
+    /// Removes the nodes deeper than `max_depth`, the root being at depth 0.
+    pub fn limit_depth(&mut self, max_depth: usize) {
+        let mut stack = vec![(Self::ROOT, 0)];
+        while let Some((index, depth)) = stack.pop() {
+            if depth == max_depth {
+                self.nodes[index].children.clear();
+            } else {
+                stack.extend(self.nodes[index].children.iter().map(|i| (*i, depth + 1)));
+            }
+        }
+    }
+
+    /// Keeps at most `max_entries` children in each directory, and none in
+    /// directories with more than `file_limit` children. Left out children
+    /// are replaced with a single node summarizing them. Returns the number
+    /// of entries left out, descendants included. Nodes without children
+    /// are directories if `is_dir` says so.
+    pub fn truncate<P>(
+        &mut self,
+        max_entries: Option<usize>,
+        file_limit: Option<usize>,
+        is_dir: P,
+    ) -> OmittedEntries
+    where
+        P: Fn(&TreeNode<'a, T>) -> bool,
+    {
+        let mut total = OmittedEntries::default();
+        let mut indices = vec![Self::ROOT];
+        while let Some(index) = indices.pop() {
+            let num_children = self.nodes[index].children.len();
+            let num_kept = if file_limit.is_some_and(|file_limit| num_children > file_limit) {
+                0
+            } else {
+                max_entries.map_or(num_children, |max_entries| max_entries.min(num_children))
+            };
+            if num_kept < num_children {
+                let omitted_indices = self.nodes[index].children.split_off(num_kept);
+                let mut omitted = OmittedEntries::default();
+                for &omitted_index in &omitted_indices {
+                    let node = &self.nodes[omitted_index];
+                    if !node.children.is_empty() || is_dir(node) {
+                        omitted.num_dirs += 1;
+                    } else {
+                        omitted.num_files += 1;
+                    }
+                }
+                total.num_dirs += omitted.num_dirs;
+                total.num_files += omitted.num_files;
+                // Count the descendants of the omitted children.
+                let mut descendant_indices = omitted_indices
+                    .iter()
+                    .flat_map(|i| &self.nodes[*i].children)
+                    .copied()
+                    .collect::<Vec<_>>();
+                while let Some(descendant_index) = descendant_indices.pop() {
+                    let node = &self.nodes[descendant_index];
+                    if !node.children.is_empty() || is_dir(node) {
+                        total.num_dirs += 1;
+                    } else {
+                        total.num_files += 1;
+                    }
+                    descendant_indices.extend(&node.children);
+                }
+                let mut summary_node = TreeNode::new(self.nodes[index].path, None, vec![]);
+                summary_node.omitted = Some(omitted);
+                self.nodes.push(summary_node);
+                let summary_index = self.nodes.len() - 1;
+                self.nodes[index].children.push(summary_index);
+            }
+            indices.extend(&self.nodes[index].children[..num_kept]);
+        }
+        total
+    }
+
+    /// Merges each chain of directories containing only one subdirectory into
+    /// a single node. The root node is left as is.
+    pub fn compact_dirs(&mut self) {
+        let mut indices = self.get_node(Self::ROOT).children.clone();
+        while let Some(index) = indices.pop() {
+            loop {
+                let child_index = match self.nodes[index].children[..] {
+                    [child_index] if !self.nodes[child_index].children.is_empty() => child_index,
+                    _ => break,
+                };
+                let child_node = &mut self.nodes[child_index];
+                let path = child_node.path;
+                let data = child_node.data.take();
+                let children = std::mem::take(&mut child_node.children);
+                let num_merged = child_node.num_merged + 1;
+                let node = &mut self.nodes[index];
+                node.path = path;
+                node.data = data;
+                node.children = children;
+                node.num_merged += num_merged;
+            }
+            indices.extend(&self.nodes[index].children);
+        }
+    }
+
+    /// Returns an iterator over the nodes of the tree in depth-first order.
+    pub fn iter(&self) -> TreeIter<'_, 'a, T> {
+        TreeIter {
+            tree: self,
+            stack: vec![(Self::ROOT, 0, true)],
+        }
+    }
+
+    /// Visits the nodes of the tree in depth-first order. Directories are
+    /// visited with `TreeVisitor::enter_dir` and `TreeVisitor::leave_dir`,
+    /// nodes standing for omitted entries with `TreeVisitor::visit_omitted`,
+    /// and others with `TreeVisitor::visit_file`. Nodes without children are
+    /// directories if `is_dir` says so.
+    pub fn visit<V, P>(&self, visitor: &mut V, is_dir: P) -> Result<(), V::Error>
+    where
+        V: TreeVisitor<'a, T>,
+        P: Fn(&TreeNode<'a, T>) -> bool,
+    {
+        // Directories entered but not left yet.
+        let mut dirs: Vec<(TreePosition, &TreeNode<'a, T>)> = vec![];
+        for (position, node) in self {
+            while let Some(&(dir_position, dir_node)) = dirs.last() {
+                if dir_position.depth < position.depth {
+                    break;
+                }
+                visitor.leave_dir(dir_position, dir_node)?;
+                dirs.pop();
+            }
+            if let Some(omitted) = node.omitted {
+                visitor.visit_omitted(position, node, omitted)?;
+            } else if !node.children.is_empty() || is_dir(node) {
+                visitor.enter_dir(position, node)?;
+                dirs.push((position, node));
+            } else {
+                visitor.visit_file(position, node)?;
             }
-            level.set_last();
-            let child_node = &self.get_node(*last_index);
-            self._for_each(func, level, child_node)?;
-            level.dedent();
+        }
+        while let Some((dir_position, dir_node)) = dirs.pop() {
+            visitor.leave_dir(dir_position, dir_node)?;
         }
         Ok(())
     }
 
+    /// Calls `func` on each node in depth-first order, with `level` set to
+    /// the indentation of the node.
     pub fn for_each<E, F, L>(&self, level: &mut L, func: &mut F) -> Result<(), E>
     where
-        F: FnMut(&L, &Path) -> Result<(), E>,
+        F: FnMut(&L, &TreeNode<'a, T>) -> Result<(), E>,
         L: IndentationLevel,
     {
-        let root_node = &self.get_node(Self::ROOT);
-        self._for_each(func, level, root_node)
+        // Directories and files are indented alike.
+        self.visit(&mut IndentingVisitor { level, func }, |_| false)
+    }
+}
+
+/// Callbacks called on the nodes of a tree by `Tree::visit`.
+pub trait TreeVisitor<'a, T> {
+    /// Error stopping the visit when returned by a callback.
+    type Error;
+
+    /// Called on a directory, before its children.
+    fn enter_dir(
+        &mut self,
+        position: TreePosition,
+        node: &TreeNode<'a, T>,
+    ) -> Result<(), Self::Error>;
+
+    /// Called on a directory, after its children.
+    fn leave_dir(
+        &mut self,
+        _position: TreePosition,
+        _node: &TreeNode<'a, T>,
+    ) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Called on a node that is not a directory, nor omitted entries.
+    fn visit_file(
+        &mut self,
+        position: TreePosition,
+        node: &TreeNode<'a, T>,
+    ) -> Result<(), Self::Error>;
+
+    /// Called on a node standing for the entries left out of its parent
+    /// directory by `Tree::truncate`. Does nothing by default.
+    fn visit_omitted(
+        &mut self,
+        _position: TreePosition,
+        _node: &TreeNode<'a, T>,
+        _omitted: OmittedEntries,
+    ) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// Visitor of `Tree::for_each`, keeping the indentation level in sync with
+/// the visited node.
+struct IndentingVisitor<'l, 'f, L, F> {
+    level: &'l mut L,
+    func: &'f mut F,
+}
+
+impl<L: IndentationLevel, F> IndentingVisitor<'_, '_, L, F> {
+    fn indent(&mut self, position: TreePosition) {
+        if position.depth > 0 {
+            self.level.indent();
+            if position.is_last {
+                self.level.set_last();
+            }
+        }
+    }
+
+    fn dedent(&mut self, position: TreePosition) {
+        if position.depth > 0 {
+            self.level.dedent();
+        }
+    }
+}
+
+impl<'a, T, E, F, L> TreeVisitor<'a, T> for IndentingVisitor<'_, '_, L, F>
+where
+    F: FnMut(&L, &TreeNode<'a, T>) -> Result<(), E>,
+    L: IndentationLevel,
+{
+    type Error = E;
+
+    fn enter_dir(&mut self, position: TreePosition, node: &TreeNode<'a, T>) -> Result<(), E> {
+        self.indent(position);
+        (self.func)(self.level, node)
+    }
+
+    fn leave_dir(&mut self, position: TreePosition, _node: &TreeNode<'a, T>) -> Result<(), E> {
+        self.dedent(position);
+        Ok(())
     }
+
+    fn visit_file(&mut self, position: TreePosition, node: &TreeNode<'a, T>) -> Result<(), E> {
+        self.indent(position);
+        let result = (self.func)(self.level, node);
+        self.dedent(position);
+        result
+    }
+
+    fn visit_omitted(
+        &mut self,
+        position: TreePosition,
+        node: &TreeNode<'a, T>,
+        _omitted: OmittedEntries,
+    ) -> Result<(), E> {
+        self.visit_file(position, node)
+    }
+}
+
+/// Position of a node within a tree.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TreePosition {
+    /// Depth of the node, the root being at depth 0.
+    pub depth: usize,
+    /// Whether the node is the last child of its parent.
+    pub is_last: bool,
 }
 
-pub struct TreeBuilder<'a> {
-    nodes: Vec<TreeNode<'a>>,
+/// Depth-first iterator over the nodes of a tree, with an explicit stack so
+/// that deep trees do not overflow the call stack.
+pub struct TreeIter<'t, 'a, T = ()> {
+    tree: &'t Tree<'a, T>,
+    stack: Vec<(TreeIndex, usize, bool)>,
+}
+
+impl<'t, 'a, T> Iterator for TreeIter<'t, 'a, T> {
+    type Item = (TreePosition, &'t TreeNode<'a, T>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (index, depth, is_last) = self.stack.pop()?;
+        let node = self.tree.get_node(index);
+        if let Some((last_index, first_indices)) = node.children.split_last() {
+            self.stack.push((*last_index, depth + 1, true));
+            self.stack
+                .extend(first_indices.iter().rev().map(|i| (*i, depth + 1, false)));
+        }
+        Some((TreePosition { depth, is_last }, node))
+    }
+}
+
+impl<'t, 'a, T> IntoIterator for &'t Tree<'a, T> {
+    type Item = (TreePosition, &'t TreeNode<'a, T>);
+    type IntoIter = TreeIter<'t, 'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Error met while building a tree from paths.
+#[derive(Debug)]
+pub enum TreeError {
+    /// No path was given.
+    Empty,
+    /// A path was not below the root, or came after itself or one of its
+    /// descendants.
+    Unsorted(PathBuf),
+}
+
+impl fmt::Display for TreeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TreeError::Empty => write!(f, "no path to build a tree from"),
+            TreeError::Unsorted(path) => {
+                write!(f, "path {} is not in depth-first order", path.display())
+            }
+        }
+    }
+}
+
+impl error::Error for TreeError {}
+
+/// Builds a tree from sorted paths.
+pub struct TreeBuilder<'a, T = ()> {
+    nodes: Vec<TreeNode<'a, T>>,
     root_depth: usize,
     indices: Vec<TreeIndex>,
 }
 
 impl<'a> TreeBuilder<'a> {
-    pub fn build(self) -> Tree<'a> {
+    /// Returns a builder for the tree of the given paths, the first one
+    /// being the root.
+    ///
+    /// The paths must be sorted in depth-first order: each path must follow
+    /// its parent directory, or one of its siblings and their descendants.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TreeError::Empty` if there is no path, and
+    /// `TreeError::Unsorted` if a path is not below the root, or comes after
+    /// itself or one of its descendants.
+    pub fn from_paths<I>(paths: I) -> Result<TreeBuilder<'a>, TreeError>
+    where
+        I: IntoIterator<Item = &'a Path>,
+    {
+        Self::from_entries(paths.into_iter().map(|path| (path, ())))
+    }
+}
+
+impl<'a, T> TreeBuilder<'a, T> {
+    /// Returns the built tree.
+    pub fn build(self) -> Tree<'a, T> {
         Tree { nodes: self.nodes }
     }
 
-    fn with_root(root_path: &'a Path) -> TreeBuilder<'a> {
-        let root_node = TreeNode::new(root_path, vec![]);
+    fn with_root(root_path: &'a Path, data: T) -> TreeBuilder<'a, T> {
+        let root_node = TreeNode::new(root_path, Some(data), vec![]);
         let root_depth = root_node.path.components().count();
         TreeBuilder {
             nodes: vec![root_node],
@@ -78,24 +647,29 @@ impl<'a> TreeBuilder<'a> {
         }
     }
 
-    pub fn from_paths<I>(paths: &mut I) -> Option<TreeBuilder<'a>>
+    /// Returns a builder for the tree of the given paths with their payload,
+    /// as `TreeBuilder::from_paths` does.
+    pub fn from_entries<I>(entries: I) -> Result<TreeBuilder<'a, T>, TreeError>
     where
-        I: Iterator<Item = &'a Path>,
+        I: IntoIterator<Item = (&'a Path, T)>,
     {
-        let mut builder = match paths.next() {
+        let mut entries = entries.into_iter();
+        let mut builder = match entries.next() {
             None => {
-                return None;
+                return Err(TreeError::Empty);
             }
-            Some(root_path) => Self::with_root(root_path),
+            Some((root_path, data)) => Self::with_root(root_path, data),
         };
-        for path in paths {
-            builder.push(path);
+        for (path, data) in entries {
+            builder.push(path, data)?;
         }
-        Some(builder)
+        Ok(builder)
     }
 
-    fn push(&mut self, path: &'a Path) {
-        // At this point, we know that path is not a root.
+    fn push(&mut self, path: &'a Path, data: T) -> Result<(), TreeError> {
+        if !path.starts_with(self.nodes[0].path) {
+            return Err(TreeError::Unsorted(path.to_path_buf()));
+        }
 
         let extra_depth = {
             // Compute the number of common components from root.
@@ -106,8 +680,9 @@ impl<'a> TreeBuilder<'a> {
             loop {
                 match (prev_components.next(), path_components.next()) {
                     (_, None) => {
-                        // Should not happend if insertion order is correct.
-                        unreachable!();
+                        // The path is the previous one or one of its
+                        // ancestors.
+                        return Err(TreeError::Unsorted(path.to_path_buf()));
                     }
                     (Some(prev_component), Some(path_component)) => {
                         if prev_component == path_component {
@@ -145,7 +720,7 @@ impl<'a> TreeBuilder<'a> {
             let mut extra_nodes = Vec::with_capacity(extra_depth);
             let ancestors = path.ancestors().skip(1).take(extra_depth);
             for (i, ancestor) in ancestors.enumerate() {
-                extra_nodes.push(TreeNode::new(ancestor, vec![path_index - i]));
+                extra_nodes.push(TreeNode::new(ancestor, None, vec![path_index - i]));
             }
             extra_nodes.reverse();
             self.nodes.append(&mut extra_nodes);
@@ -154,8 +729,197 @@ impl<'a> TreeBuilder<'a> {
             }
         }
 
-        let path_node = TreeNode::new(path, vec![]);
+        let path_node = TreeNode::new(path, Some(data), vec![]);
         self.nodes.push(path_node);
         self.indices.push(path_index);
+        Ok(())
+    }
+}
+
+#[test]
+fn test_tree_iter() {
+    let paths = ["a", "a/b", "a/b/c", "a/d", "a/e", "a/e/f"];
+    let tree = TreeBuilder::from_paths(paths.iter().map(Path::new))
+        .unwrap()
+        .build();
+    let items = tree
+        .iter()
+        .map(|(position, node)| {
+            (
+                node.path().to_str().unwrap(),
+                position.depth,
+                position.is_last,
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        items,
+        [
+            ("a", 0, true),
+            ("a/b", 1, false),
+            ("a/b/c", 2, true),
+            ("a/d", 1, false),
+            ("a/e", 1, true),
+            ("a/e/f", 2, true),
+        ]
+    );
+}
+
+#[test]
+fn test_tree_visit() {
+    struct EventVisitor(Vec<String>);
+
+    impl<'a> TreeVisitor<'a, bool> for EventVisitor {
+        type Error = ();
+
+        fn enter_dir(
+            &mut self,
+            position: TreePosition,
+            node: &TreeNode<'a, bool>,
+        ) -> Result<(), ()> {
+            let path = node.path().display();
+            self.0.push(format!("enter {} {}", path, position.depth));
+            Ok(())
+        }
+
+        fn leave_dir(
+            &mut self,
+            _position: TreePosition,
+            node: &TreeNode<'a, bool>,
+        ) -> Result<(), ()> {
+            self.0.push(format!("leave {}", node.path().display()));
+            Ok(())
+        }
+
+        fn visit_file(
+            &mut self,
+            position: TreePosition,
+            node: &TreeNode<'a, bool>,
+        ) -> Result<(), ()> {
+            let path = node.path().display();
+            self.0.push(format!("file {} {}", path, position.is_last));
+            Ok(())
+        }
+
+        fn visit_omitted(
+            &mut self,
+            position: TreePosition,
+            node: &TreeNode<'a, bool>,
+            omitted: OmittedEntries,
+        ) -> Result<(), ()> {
+            let path = node.path().display();
+            self.0.push(format!(
+                "omitted {} {} {} {}",
+                path,
+                position.depth,
+                omitted.num_dirs(),
+                omitted.num_files()
+            ));
+            Ok(())
+        }
+    }
+
+    fn visit_events(tree: &Tree<bool>) -> Vec<String> {
+        let mut visitor = EventVisitor(vec![]);
+        tree.visit(&mut visitor, |node| node.data() == Some(&true))
+            .unwrap();
+        visitor.0
+    }
+
+    // Directories are given by the payload, so that empty ones are entered.
+    let entries = [
+        ("a", true),
+        ("a/b", true),
+        ("a/b/c", false),
+        ("a/d", true),
+        ("a/e", true),
+        ("a/e/f", false),
+        ("a/g", false),
+    ];
+    let mut tree = TreeBuilder::from_entries(entries.iter().map(|(p, d)| (Path::new(p), *d)))
+        .unwrap()
+        .build();
+    assert_eq!(
+        visit_events(&tree),
+        [
+            "enter a 0",
+            "enter a/b 1",
+            "file a/b/c true",
+            "leave a/b",
+            "enter a/d 1",
+            "leave a/d",
+            "enter a/e 1",
+            "file a/e/f true",
+            "leave a/e",
+            "file a/g true",
+            "leave a",
+        ]
+    );
+    tree.truncate(Some(2), None, |node| node.data() == Some(&true));
+    assert_eq!(
+        visit_events(&tree),
+        [
+            "enter a 0",
+            "enter a/b 1",
+            "file a/b/c true",
+            "leave a/b",
+            "enter a/d 1",
+            "leave a/d",
+            "omitted a 1 1 1",
+            "leave a",
+        ]
+    );
+
+    // An empty root directory is entered and left.
+    let tree = TreeBuilder::from_entries([(Path::new("a"), true)])
+        .unwrap()
+        .build();
+    assert_eq!(visit_events(&tree), ["enter a 0", "leave a"]);
+}
+
+#[test]
+fn test_tree_transform() {
+    let paths = ["a", "a/b", "a/b/c.rs", "a/d", "a/e.txt", "a/f.rs"];
+    let mut tree = TreeBuilder::from_entries(paths.iter().map(|path| (Path::new(path), *path)))
+        .unwrap()
+        .build()
+        .map(|path| path.len());
+    let sizes = tree.fold(|node, children: &[&usize]| {
+        node.data().copied().unwrap_or_default() + children.iter().copied().sum::<usize>()
+    });
+    assert_eq!(sizes.root().data(), Some(&(1 + 3 + 8 + 3 + 7 + 6)));
+    fn tree_paths<'a>(tree: &Tree<'a, usize>) -> Vec<&'a str> {
+        tree.iter()
+            .map(|(_, node)| node.path().to_str().unwrap())
+            .collect()
+    }
+    tree.prune(|node| node.path().extension().is_none());
+    assert_eq!(
+        tree_paths(&tree),
+        ["a", "a/b", "a/b/c.rs", "a/e.txt", "a/f.rs"]
+    );
+    tree.filter(|node| node.path().extension().is_some_and(|ext| ext == "rs"));
+    assert_eq!(tree_paths(&tree), ["a", "a/b", "a/b/c.rs", "a/f.rs"]);
+    let mut excluded = vec![];
+    tree.exclude(|node| {
+        excluded.push(node.path().to_str().unwrap());
+        node.path() == Path::new("a/b")
+    });
+    assert_eq!(excluded, ["a/b", "a/f.rs"]);
+    assert_eq!(tree_paths(&tree), ["a", "a/f.rs"]);
+}
+
+#[test]
+fn test_tree_builder_errors() {
+    fn build_error(paths: &[&str]) -> Option<TreeError> {
+        TreeBuilder::from_paths(paths.iter().map(Path::new)).err()
+    }
+    assert!(matches!(build_error(&[]), Some(TreeError::Empty)));
+    assert!(build_error(&["a", "a/b", "a/b/c", "a/d"]).is_none());
+    for paths in [&["a", "a"][..], &["a", "a/b", "a/b/c", "a/b"], &["a", "b"]] {
+        match build_error(paths) {
+            Some(TreeError::Unsorted(path)) => assert_eq!(path, Path::new(paths[paths.len() - 1])),
+            err => panic!("unexpected result for {:?}: {:?}", paths, err),
+        }
     }
 }
@@ -0,0 +1,142 @@
+//! Plain text rendering of path trees.
+
+use std::borrow::Cow;
+use std::ffi::OsStr;
+use std::fmt;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::indent::IndentationLevel;
+use crate::pathtree::{DirCounts, OmittedEntries, Tree};
+
+/// Returns the singular form of a noun if `count` is 1, else its plural form.
+pub fn pluralize<N>(count: N, singular: &'static str, plural: &'static str) -> &'static str
+where
+    N: PartialEq + From<u8>,
+{
+    if count == N::from(1) {
+        singular
+    } else {
+        plural
+    }
+}
+
+/// Returns the label of a path in a tree: the whole path if `print_path` is
+/// set, else its file name, preceded by the names of the `num_merged`
+/// directories merged into its node.
+pub fn path_label(path: &Path, num_merged: usize, print_path: bool) -> Cow<'_, str> {
+    if print_path {
+        path.to_string_lossy()
+    } else if num_merged > 0 {
+        let num_components = path.components().count();
+        let label = path
+            .components()
+            .skip(num_components.saturating_sub(num_merged + 1))
+            .collect::<PathBuf>();
+        label.to_string_lossy().into_owned().into()
+    } else {
+        path.file_name()
+            .map(OsStr::to_string_lossy)
+            .unwrap_or_else(|| "..".into())
+    }
+}
+
+/// Writes the line standing for the entries left out of a directory.
+pub fn write_omitted_entries<L, W>(
+    output: &mut W,
+    level: &L,
+    omitted: OmittedEntries,
+) -> io::Result<()>
+where
+    L: IndentationLevel,
+    W: Write,
+{
+    write!(output, "{}…", level)?;
+    let num_files = omitted.num_files();
+    let num_dirs = omitted.num_dirs();
+    if num_files != 0 {
+        write!(
+            output,
+            " {} more {}",
+            num_files,
+            pluralize(num_files, "file", "files")
+        )?;
+    }
+    if num_dirs != 0 {
+        if num_files != 0 {
+            write!(output, ",")?;
+        }
+        write!(
+            output,
+            " {} more {}",
+            num_dirs,
+            pluralize(num_dirs, "directory", "directories")
+        )?;
+    }
+    writeln!(output)?;
+    Ok(())
+}
+
+/// Writes a tree, one node per line: the whole path of the root, then the
+/// label of each descendant after its indentation. The payloads of the nodes
+/// are not written.
+pub fn write_tree<T, L, W>(output: &mut W, tree: &Tree<T>, level: &mut L) -> io::Result<()>
+where
+    L: IndentationLevel,
+    W: Write,
+{
+    tree.for_each(level, &mut |level, node| {
+        if let Some(omitted) = node.omitted() {
+            return write_omitted_entries(output, level, omitted);
+        }
+        let label = path_label(node.path(), node.num_merged(), level.is_empty());
+        writeln!(output, "{}{}", level, label)
+    })
+}
+
+impl fmt::Display for DirCounts {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (num_dirs, num_files) = (self.num_dirs(), self.num_files());
+        let (total_dirs, total_files) = (self.total_dirs(), self.total_files());
+        if total_dirs == 0 && total_files == 0 {
+            return write!(f, "(empty)");
+        }
+        write!(
+            f,
+            "({} {}, {} {}",
+            num_dirs,
+            pluralize(num_dirs, "directory", "directories"),
+            num_files,
+            pluralize(num_files, "file", "files")
+        )?;
+        if num_dirs != 0 {
+            write!(
+                f,
+                "; {} {}, {} {} in total",
+                total_dirs,
+                pluralize(total_dirs, "directory", "directories"),
+                total_files,
+                pluralize(total_files, "file", "files")
+            )?;
+        }
+        write!(f, ")")
+    }
+}
+
+#[test]
+fn test_write_tree_with_payloads() {
+    use crate::indent::{AsciiMarks, TreeLevel};
+    use crate::pathtree::TreeBuilder;
+
+    let entries = [("a", 1), ("a/b", 2), ("a/b/c", 3), ("a/d", 4)];
+    let tree =
+        TreeBuilder::from_entries(entries.iter().map(|(path, size)| (Path::new(path), size)))
+            .unwrap()
+            .build();
+    let mut output = vec![];
+    write_tree(&mut output, &tree, &mut TreeLevel::<AsciiMarks>::new()).unwrap();
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "a\n|-- b\n|   `-- c\n`-- d\n"
+    );
+}
@@ -0,0 +1,322 @@
+use std::ffi::OsStr;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+use git2::{ObjectType, Odb, Oid, Repository, Tree};
+use ignore::overrides::Override;
+
+const MODE_LINK: i32 = 0o120000;
+const MODE_EXECUTABLE: i32 = 0o100755;
+
+/// Returns whether `path` is a bare repository or a `.git` directory.
+pub fn is_git_dir(path: &Path) -> bool {
+    path.join("HEAD").is_file() && path.join("objects").is_dir() && path.join("refs").is_dir()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RepoEntryKind {
+    Tree,
+    Blob,
+    Executable,
+    Link,
+    Submodule,
+}
+
+impl RepoEntryKind {
+    pub fn is_dir(self) -> bool {
+        matches!(self, Self::Tree | Self::Submodule)
+    }
+}
+
+pub struct RepoEntry {
+    path: PathBuf,
+    id: Oid,
+    kind: RepoEntryKind,
+    size: Option<u64>,
+    target: Option<PathBuf>,
+}
+
+impl RepoEntry {
+    #[inline]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the identifier of the Git object of the entry.
+    #[inline]
+    pub fn id(&self) -> Oid {
+        self.id
+    }
+
+    #[inline]
+    pub fn kind(&self) -> RepoEntryKind {
+        self.kind
+    }
+
+    /// Returns the size of blob entries, if read by the walk, and `None` for
+    /// other entries.
+    #[inline]
+    pub fn size(&self) -> Option<u64> {
+        self.size
+    }
+
+    /// Returns the target of a symbolic link entry.
+    #[inline]
+    pub fn target(&self) -> Option<&Path> {
+        self.target.as_deref()
+    }
+}
+
+/// Lists the entries of a revision of a Git repository, the way
+/// `ignore::WalkBuilder` lists the entries of a working directory.
+pub struct RepoWalkBuilder {
+    git_dir: PathBuf,
+    revision: String,
+    hidden: bool,
+    sizes: bool,
+    max_depth: Option<usize>,
+    overrides: Override,
+}
+
+impl RepoWalkBuilder {
+    pub fn new<P: AsRef<Path>>(git_dir: P) -> Self {
+        RepoWalkBuilder {
+            git_dir: git_dir.as_ref().to_path_buf(),
+            revision: "HEAD".to_owned(),
+            hidden: true,
+            sizes: true,
+            max_depth: None,
+            overrides: Override::empty(),
+        }
+    }
+
+    pub fn revision(&mut self, revision: &str) -> &mut Self {
+        self.revision = revision.to_owned();
+        self
+    }
+
+    /// Skips hidden entries if `yes` is true.
+    pub fn hidden(&mut self, yes: bool) -> &mut Self {
+        self.hidden = yes;
+        self
+    }
+
+    /// Reads the size of blobs if `yes` is true. This needs to look up the
+    /// header of every blob.
+    pub fn sizes(&mut self, yes: bool) -> &mut Self {
+        self.sizes = yes;
+        self
+    }
+
+    pub fn max_depth(&mut self, depth: Option<usize>) -> &mut Self {
+        self.max_depth = depth;
+        self
+    }
+
+    pub fn overrides(&mut self, overrides: Override) -> &mut Self {
+        self.overrides = overrides;
+        self
+    }
+
+    /// Returns the entries of the revision in depth-first order, starting
+    /// with the repository itself.
+    pub fn build(&self) -> Result<Vec<RepoEntry>, git2::Error> {
+        let repo = Repository::open_bare(&self.git_dir)?;
+        let odb = repo.odb()?;
+        let tree = repo.revparse_single(&self.revision)?.peel_to_tree()?;
+        let mut entries = vec![RepoEntry {
+            path: self.git_dir.clone(),
+            id: tree.id(),
+            kind: RepoEntryKind::Tree,
+            size: None,
+            target: None,
+        }];
+        self.walk(&repo, &odb, tree, &mut entries)?;
+        Ok(entries)
+    }
+
+    /// Adds the entries of the root tree and of its subtrees, each tree
+    /// followed by its entries, with a stack rather than recursion, so that
+    /// deep trees cannot overflow the call stack.
+    fn walk<'r>(
+        &self,
+        repo: &'r Repository,
+        odb: &Odb,
+        root_tree: Tree<'r>,
+        entries: &mut Vec<RepoEntry>,
+    ) -> Result<(), git2::Error> {
+        if self.max_depth == Some(0) {
+            return Ok(());
+        }
+        // Trees being walked, with their path, the depth of their entries
+        // and the index of their next entry.
+        let mut stack = vec![(root_tree, self.git_dir.clone(), 1, 0)];
+        while let Some((tree, dir_path, depth, index)) = stack.last_mut() {
+            let tree_entry = match tree.get(*index).map(|e| e.to_owned()) {
+                Some(tree_entry) => tree_entry,
+                None => {
+                    stack.pop();
+                    continue;
+                }
+            };
+            *index += 1;
+            let file_name = OsStr::from_bytes(tree_entry.name_bytes());
+            if self.hidden && file_name.as_bytes().starts_with(b".") {
+                continue;
+            }
+            let kind = match tree_entry.kind() {
+                Some(ObjectType::Tree) => RepoEntryKind::Tree,
+                Some(ObjectType::Commit) => RepoEntryKind::Submodule,
+                _ => match tree_entry.filemode() {
+                    MODE_LINK => RepoEntryKind::Link,
+                    MODE_EXECUTABLE => RepoEntryKind::Executable,
+                    _ => RepoEntryKind::Blob,
+                },
+            };
+            let path = dir_path.join(file_name);
+            if self.overrides.matched(&path, kind.is_dir()).is_ignore() {
+                continue;
+            }
+            let id = tree_entry.id();
+            let (size, target) = match kind {
+                RepoEntryKind::Tree | RepoEntryKind::Submodule => (None, None),
+                RepoEntryKind::Link => {
+                    let blob = repo.find_blob(id)?;
+                    let target = PathBuf::from(OsStr::from_bytes(blob.content()));
+                    (Some(blob.size() as u64), Some(target))
+                }
+                RepoEntryKind::Blob | RepoEntryKind::Executable if self.sizes => {
+                    let (size, _) = odb.read_header(id)?;
+                    (Some(size as u64), None)
+                }
+                RepoEntryKind::Blob | RepoEntryKind::Executable => (None, None),
+            };
+            let subtree_depth = *depth + 1;
+            entries.push(RepoEntry {
+                path: path.clone(),
+                id,
+                kind,
+                size,
+                target,
+            });
+            let in_depth = self
+                .max_depth
+                .is_none_or(|max_depth| subtree_depth <= max_depth);
+            if kind == RepoEntryKind::Tree && in_depth {
+                stack.push((repo.find_tree(id)?, path, subtree_depth, 0));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Writes the tree of the given files, with paths separated by slashes, and
+/// returns its identifier.
+#[cfg(test)]
+fn write_test_tree(repo: &Repository, files: &[(&str, i32, &str)]) -> Oid {
+    let mut builder = repo.treebuilder(None).unwrap();
+    let mut dirs = std::collections::BTreeMap::<_, Vec<_>>::new();
+    for (path, mode, contents) in files {
+        match path.split_once('/') {
+            Some((dir, path)) => dirs.entry(dir).or_default().push((path, *mode, *contents)),
+            None => {
+                let blob_id = repo.blob(contents.as_bytes()).unwrap();
+                builder.insert(path, blob_id, *mode).unwrap();
+            }
+        }
+    }
+    for (dir, files) in dirs {
+        let tree_id = write_test_tree(repo, &files);
+        builder.insert(dir, tree_id, 0o040000).unwrap();
+    }
+    builder.write().unwrap()
+}
+
+/// Commits the given files to the current branch of the repository.
+#[cfg(test)]
+pub fn commit_test_files(repo: &Repository, files: &[(&str, i32, &str)]) {
+    let tree = repo.find_tree(write_test_tree(repo, files)).unwrap();
+    let signature = git2::Signature::now("gitree", "gitree@example.com").unwrap();
+    let parent = repo.head().ok().map(|head| head.peel_to_commit().unwrap());
+    let parents = parent.iter().collect::<Vec<_>>();
+    repo.commit(Some("HEAD"), &signature, &signature, "", &tree, &parents)
+        .unwrap();
+}
+
+#[test]
+fn test_repo_walk() {
+    let git_dir = std::env::temp_dir().join(format!("gitree-repo-walk-{}.git", std::process::id()));
+    let _ = std::fs::remove_dir_all(&git_dir);
+    let repo = Repository::init_bare(&git_dir).unwrap();
+    commit_test_files(
+        &repo,
+        &[("a/b/c.txt", 0o100644, "c"), ("d.txt", 0o100644, "")],
+    );
+    commit_test_files(
+        &repo,
+        &[
+            (".hidden", 0o100644, ""),
+            ("a/b/c.txt", 0o100644, "cc"),
+            ("a/e", MODE_LINK, "b/c.txt"),
+            ("run.sh", MODE_EXECUTABLE, "#!/bin/sh\n"),
+        ],
+    );
+    let walk = |builder: &mut RepoWalkBuilder| {
+        builder
+            .build()
+            .unwrap()
+            .iter()
+            .map(|e| {
+                let path = e.path().strip_prefix(&git_dir).unwrap().to_str().unwrap();
+                (
+                    path.to_owned(),
+                    e.kind(),
+                    e.size(),
+                    e.target().map(Path::to_path_buf),
+                )
+            })
+            .collect::<Vec<_>>()
+    };
+    let entry = |path: &str, kind, size, target: Option<&str>| {
+        (path.to_owned(), kind, size, target.map(PathBuf::from))
+    };
+    assert_eq!(
+        walk(&mut RepoWalkBuilder::new(&git_dir)),
+        [
+            entry("", RepoEntryKind::Tree, None, None),
+            entry("a", RepoEntryKind::Tree, None, None),
+            entry("a/b", RepoEntryKind::Tree, None, None),
+            entry("a/b/c.txt", RepoEntryKind::Blob, Some(2), None),
+            entry("a/e", RepoEntryKind::Link, Some(7), Some("b/c.txt")),
+            entry("run.sh", RepoEntryKind::Executable, Some(10), None),
+        ]
+    );
+    assert_eq!(
+        walk(
+            RepoWalkBuilder::new(&git_dir)
+                .hidden(false)
+                .max_depth(Some(1))
+        ),
+        [
+            entry("", RepoEntryKind::Tree, None, None),
+            entry(".hidden", RepoEntryKind::Blob, Some(0), None),
+            entry("a", RepoEntryKind::Tree, None, None),
+            entry("run.sh", RepoEntryKind::Executable, Some(10), None),
+        ]
+    );
+    assert_eq!(
+        walk(
+            RepoWalkBuilder::new(&git_dir)
+                .revision("HEAD~1")
+                .sizes(false)
+        ),
+        [
+            entry("", RepoEntryKind::Tree, None, None),
+            entry("a", RepoEntryKind::Tree, None, None),
+            entry("a/b", RepoEntryKind::Tree, None, None),
+            entry("a/b/c.txt", RepoEntryKind::Blob, None, None),
+            entry("d.txt", RepoEntryKind::Blob, None, None),
+        ]
+    );
+    std::fs::remove_dir_all(&git_dir).unwrap();
+}
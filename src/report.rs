@@ -1,10 +1,92 @@
+use std::collections::HashMap;
 use std::fmt;
-use std::fs::FileType;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+use crate::entry::Entry;
+use crate::render::pluralize;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReportKind {
+    Dir,
+    File,
+    Symlink { target_is_dir: bool },
+    BrokenSymlink,
+    Special,
+}
+
+impl ReportKind {
+    /// Returns whether the entry counts as a directory, as symbolic links to
+    /// directories do.
+    pub fn is_dir(self) -> bool {
+        matches!(
+            self,
+            ReportKind::Dir
+                | ReportKind::Symlink {
+                    target_is_dir: true
+                }
+        )
+    }
+}
+
+/// What the report needs to know about an entry.
+pub struct ReportEntry<'a> {
+    pub path: &'a Path,
+    pub kind: ReportKind,
+    pub executable: bool,
+    pub size: u64,
+}
+
+impl<'a> ReportEntry<'a> {
+    /// Returns what the report needs to know about an entry which is not
+    /// printed, such as entries left out of the tree or merged directories.
+    pub fn from_entry<E: Entry>(entry: &'a E) -> Self {
+        let path = entry.path();
+        let kind = if entry.is_symlink() {
+            match path.metadata() {
+                Ok(metadata) => ReportKind::Symlink {
+                    target_is_dir: metadata.is_dir(),
+                },
+                Err(_) if path.symlink_metadata().is_ok() => ReportKind::BrokenSymlink,
+                // Not on disk, such as entries of a Git revision.
+                Err(_) => ReportKind::Symlink {
+                    target_is_dir: false,
+                },
+            }
+        } else if entry.is_dir() {
+            ReportKind::Dir
+        } else if entry.is_file() {
+            ReportKind::File
+        } else {
+            ReportKind::Special
+        };
+        ReportEntry {
+            path,
+            kind,
+            executable: entry.is_executable(),
+            size: entry.file_size().unwrap_or(0),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct ExtensionStats {
+    num_files: usize,
+    num_bytes: u64,
+}
 
 #[derive(Default)]
 pub struct Report {
     num_dirs: usize,
     num_files: usize,
+    num_symlinks: usize,
+    num_broken_symlinks: usize,
+    num_executables: usize,
+    num_hidden: usize,
+    num_special: usize,
+    num_bytes: u64,
+    num_errors: usize,
+    extensions: HashMap<String, ExtensionStats>,
 }
 
 impl Report {
@@ -12,14 +94,103 @@ impl Report {
         Self::default()
     }
 
-    pub fn add(&mut self, toplevel: bool, file_type: FileType) {
-        if !toplevel {
-            if file_type.is_dir() {
-                self.num_dirs += 1;
-            } else {
-                self.num_files += 1;
+    pub fn add(&mut self, toplevel: bool, entry: &ReportEntry) {
+        if toplevel {
+            return;
+        }
+        if entry.kind.is_dir() {
+            self.num_dirs += 1;
+        } else {
+            self.num_files += 1;
+        }
+        match entry.kind {
+            ReportKind::Symlink { .. } => self.num_symlinks += 1,
+            ReportKind::BrokenSymlink => {
+                self.num_symlinks += 1;
+                self.num_broken_symlinks += 1;
             }
+            ReportKind::Special => self.num_special += 1,
+            ReportKind::File => {
+                let extension = entry
+                    .path
+                    .extension()
+                    .map(|e| e.to_string_lossy().to_lowercase())
+                    .unwrap_or_default();
+                let stats = self.extensions.entry(extension).or_default();
+                stats.num_files += 1;
+                stats.num_bytes += entry.size;
+                self.num_bytes += entry.size;
+            }
+            ReportKind::Dir => {}
+        }
+        if entry.executable {
+            self.num_executables += 1;
+        }
+        let is_hidden = entry
+            .path
+            .file_name()
+            .is_some_and(|name| name.as_bytes().starts_with(b"."));
+        if is_hidden {
+            self.num_hidden += 1;
+        }
+    }
+
+    /// Counts an entry that could not be read.
+    pub fn add_error(&mut self) {
+        self.num_errors += 1;
+    }
+
+    #[inline]
+    pub fn num_errors(&self) -> usize {
+        self.num_errors
+    }
+
+    /// Returns the report with the detailed counts and the breakdown by
+    /// extension.
+    pub fn full(&self) -> FullReport<'_> {
+        FullReport(self)
+    }
+
+    /// Returns the report as a JSON object, with the detailed counts and the
+    /// breakdown by extension if `full` is true.
+    pub fn json(&self, full: bool) -> JsonReport<'_> {
+        JsonReport(self, full)
+    }
+
+    /// Returns the report as `key=value` lines, with the detailed counts and
+    /// the breakdown by extension if `full` is true.
+    pub fn key_values(&self, full: bool) -> KeyValueReport<'_> {
+        KeyValueReport(self, full)
+    }
+
+    fn counts(&self, full: bool) -> Vec<(&'static str, u64)> {
+        let mut counts = vec![
+            ("directories", self.num_dirs as u64),
+            ("files", self.num_files as u64),
+            ("errors", self.num_errors as u64),
+        ];
+        if full {
+            counts.extend([
+                ("symlinks", self.num_symlinks as u64),
+                ("broken_symlinks", self.num_broken_symlinks as u64),
+                ("executables", self.num_executables as u64),
+                ("hidden", self.num_hidden as u64),
+                ("special", self.num_special as u64),
+                ("bytes", self.num_bytes),
+            ]);
         }
+        counts
+    }
+
+    fn sorted_extensions(&self) -> Vec<(&String, &ExtensionStats)> {
+        let mut extensions = self.extensions.iter().collect::<Vec<_>>();
+        extensions.sort_by(|(extension_1, stats_1), (extension_2, stats_2)| {
+            stats_2
+                .num_files
+                .cmp(&stats_1.num_files)
+                .then_with(|| extension_1.cmp(extension_2))
+        });
+        extensions
     }
 }
 
@@ -29,13 +200,180 @@ impl fmt::Display for Report {
             f,
             "{} {}, {} {}",
             self.num_dirs,
-            if self.num_dirs == 1 {
-                "directory"
-            } else {
-                "directories"
-            },
+            pluralize(self.num_dirs, "directory", "directories"),
             self.num_files,
-            if self.num_files == 1 { "file" } else { "files" }
-        )
+            pluralize(self.num_files, "file", "files")
+        )?;
+        if self.num_errors != 0 {
+            write!(
+                f,
+                ", {} {}",
+                self.num_errors,
+                pluralize(self.num_errors, "error", "errors")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+pub struct FullReport<'a>(&'a Report);
+
+impl fmt::Display for FullReport<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let report = self.0;
+        writeln!(f, "{}", report)?;
+        writeln!(
+            f,
+            "{} symbolic {} ({} broken), {} {}, {} hidden, {} special",
+            report.num_symlinks,
+            pluralize(report.num_symlinks, "link", "links"),
+            report.num_broken_symlinks,
+            report.num_executables,
+            pluralize(report.num_executables, "executable", "executables"),
+            report.num_hidden,
+            report.num_special,
+        )?;
+        write!(
+            f,
+            "{} {} in files",
+            report.num_bytes,
+            pluralize(report.num_bytes, "byte", "bytes")
+        )?;
+        if report.extensions.is_empty() {
+            return Ok(());
+        }
+        let extensions = report.sorted_extensions();
+        let width = extensions
+            .iter()
+            .map(|(extension, _)| extension.chars().count().max("(none)".len()))
+            .max()
+            .unwrap_or_default()
+            .max("Extension".len());
+        write!(
+            f,
+            "\n\n{:<width$}  {:>8}  {:>12}",
+            "Extension", "Files", "Bytes"
+        )?;
+        for (extension, stats) in extensions {
+            let extension = if extension.is_empty() {
+                "(none)"
+            } else {
+                extension
+            };
+            write!(
+                f,
+                "\n{:<width$}  {:>8}  {:>12}",
+                extension, stats.num_files, stats.num_bytes
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn write_json_string(f: &mut fmt::Formatter, value: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in value.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            c if c.is_control() => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
     }
+    write!(f, "\"")
+}
+
+pub struct JsonReport<'a>(&'a Report, bool);
+
+impl fmt::Display for JsonReport<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let JsonReport(report, full) = *self;
+        write!(f, "{{")?;
+        for (i, (key, count)) in report.counts(full).into_iter().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+            write!(f, "\"{}\":{}", key, count)?;
+        }
+        if full {
+            write!(f, ",\"extensions\":{{")?;
+            for (i, (extension, stats)) in report.sorted_extensions().into_iter().enumerate() {
+                if i > 0 {
+                    write!(f, ",")?;
+                }
+                write_json_string(f, extension)?;
+                write!(
+                    f,
+                    ":{{\"files\":{},\"bytes\":{}}}",
+                    stats.num_files, stats.num_bytes
+                )?;
+            }
+            write!(f, "}}")?;
+        }
+        write!(f, "}}")
+    }
+}
+
+pub struct KeyValueReport<'a>(&'a Report, bool);
+
+impl fmt::Display for KeyValueReport<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let KeyValueReport(report, full) = *self;
+        for (i, (key, count)) in report.counts(full).into_iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}={}", key, count)?;
+        }
+        if full {
+            for (extension, stats) in report.sorted_extensions() {
+                let extension = if extension.is_empty() {
+                    "(none)"
+                } else {
+                    extension
+                };
+                write!(
+                    f,
+                    "\nextension.{}.files={}\nextension.{}.bytes={}",
+                    extension, stats.num_files, extension, stats.num_bytes
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_json_report() {
+    let mut report = Report::new();
+    report.add(
+        false,
+        &ReportEntry {
+            path: Path::new("a/b.RS"),
+            kind: ReportKind::File,
+            executable: false,
+            size: 3,
+        },
+    );
+    report.add(
+        false,
+        &ReportEntry {
+            path: Path::new("a"),
+            kind: ReportKind::Dir,
+            executable: false,
+            size: 0,
+        },
+    );
+    assert_eq!(
+        report.json(false).to_string(),
+        r#"{"directories":1,"files":1,"errors":0}"#
+    );
+    assert_eq!(
+        report.json(true).to_string(),
+        concat!(
+            r#"{"directories":1,"files":1,"errors":0,"symlinks":0,"broken_symlinks":0,"#,
+            r#""executables":0,"hidden":0,"special":0,"bytes":3,"#,
+            r#""extensions":{"rs":{"files":1,"bytes":3}}}"#
+        )
+    );
 }
@@ -0,0 +1,115 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+
+use regex::bytes::{Regex, RegexBuilder};
+
+/// Summary of the lines of a file matching a content search.
+pub struct ContentMatch {
+    count: usize,
+    first_line_number: usize,
+    first_line: String,
+}
+
+impl ContentMatch {
+    /// Returns the number of matching lines.
+    #[inline]
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Returns the 1-based number of the first matching line.
+    #[inline]
+    pub fn first_line_number(&self) -> usize {
+        self.first_line_number
+    }
+
+    #[inline]
+    pub fn first_line(&self) -> &str {
+        &self.first_line
+    }
+}
+
+pub struct ContentSearch {
+    regex: Regex,
+}
+
+impl ContentSearch {
+    pub fn new(pattern: &str, case_insensitive: bool) -> Result<Self, regex::Error> {
+        let regex = RegexBuilder::new(pattern)
+            .case_insensitive(case_insensitive)
+            .build()?;
+        Ok(ContentSearch { regex })
+    }
+
+    /// Searches contents line by line, as they are read. Binary contents,
+    /// recognized by a NUL byte in the first chunk read, never match.
+    pub fn search_reader<R: BufRead>(&self, mut reader: R) -> io::Result<Option<ContentMatch>> {
+        if reader.fill_buf()?.contains(&0) {
+            return Ok(None);
+        }
+        let mut content_match: Option<ContentMatch> = None;
+        let mut line = vec![];
+        let mut line_number = 0;
+        while reader.read_until(b'\n', &mut line)? != 0 {
+            line_number += 1;
+            if line.last() == Some(&b'\n') {
+                line.pop();
+            }
+            if self.regex.is_match(&line) {
+                match content_match {
+                    Some(ref mut content_match) => content_match.count += 1,
+                    None => {
+                        content_match = Some(ContentMatch {
+                            count: 1,
+                            first_line_number: line_number,
+                            first_line: String::from_utf8_lossy(&line).trim().to_owned(),
+                        })
+                    }
+                }
+            }
+            line.clear();
+        }
+        Ok(content_match)
+    }
+
+    /// Searches contents held in memory, such as Git blobs.
+    pub fn search(&self, contents: &[u8]) -> Option<ContentMatch> {
+        // Reading from a slice cannot fail.
+        self.search_reader(contents).unwrap()
+    }
+
+    /// Searches the contents of a file, which never match if it cannot be
+    /// read.
+    pub fn search_file(&self, path: &Path) -> Option<ContentMatch> {
+        let file = File::open(path).ok()?;
+        self.search_reader(BufReader::new(file)).ok()?
+    }
+}
+
+#[test]
+fn test_content_search() {
+    let search = ContentSearch::new("fo+", false).unwrap();
+
+    let content_match = search.search(b"bar\n  foo();\nfoooo\n").unwrap();
+    assert_eq!(content_match.count(), 2);
+    assert_eq!(content_match.first_line_number(), 2);
+    assert_eq!(content_match.first_line(), "foo();");
+
+    // No match.
+    assert!(search.search(b"bar\nbaz\n").is_none());
+
+    // Binary contents.
+    assert!(search.search(b"foo\0bar").is_none());
+
+    // Only the first chunk is looked at to recognize binary contents.
+    let mut contents = b"foo\n".repeat(3000);
+    assert!(search.search_reader(&contents[..]).unwrap().is_some());
+    contents[4] = 0;
+    let reader = BufReader::with_capacity(8, &contents[..]);
+    assert!(search.search_reader(reader).unwrap().is_none());
+    contents[4] = b'f';
+    contents[9002] = 0;
+    let reader = BufReader::with_capacity(8, &contents[..]);
+    assert_eq!(search.search_reader(reader).unwrap().unwrap().count(), 3000);
+}
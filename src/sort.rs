@@ -0,0 +1,169 @@
+use std::cmp::Ordering;
+use std::ffi::OsStr;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::time::SystemTime;
+
+use crate::cli::{Collation, SortKey};
+use crate::entry::Entry;
+use crate::utils::{compare_file_names, compare_versions, CollationKey};
+
+fn file_name(path: &Path) -> &OsStr {
+    path.file_name().unwrap_or(path.as_os_str())
+}
+
+fn compare_extensions(path_1: &Path, path_2: &Path) -> Ordering {
+    let extension_1 = path_1.extension().unwrap_or_default().as_bytes();
+    let extension_2 = path_2.extension().unwrap_or_default().as_bytes();
+    extension_1
+        .iter()
+        .map(u8::to_ascii_lowercase)
+        .cmp(extension_2.iter().map(u8::to_ascii_lowercase))
+}
+
+/// Grouping of directories and files within each directory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Grouping {
+    None,
+    DirsFirst,
+    FilesFirst,
+}
+
+/// Sort keys computed once per entry, so that metadata is not fetched on
+/// every comparison.
+enum Keys {
+    Sizes(Vec<u64>),
+    Times(Vec<Option<SystemTime>>),
+    None,
+}
+
+impl Keys {
+    fn new<E: Entry>(entries: &[E], sort_key: SortKey) -> Self {
+        match sort_key {
+            SortKey::Size => {
+                Keys::Sizes(entries.iter().map(|e| e.file_size().unwrap_or(0)).collect())
+            }
+            SortKey::Mtime => Keys::Times(entries.iter().map(Entry::modified).collect()),
+            SortKey::Ctime => Keys::Times(entries.iter().map(Entry::changed).collect()),
+            _ => Keys::None,
+        }
+    }
+
+    fn compare(&self, index_1: usize, index_2: usize) -> Ordering {
+        match self {
+            Keys::Sizes(sizes) => sizes[index_1].cmp(&sizes[index_2]),
+            Keys::Times(times) => times[index_1].cmp(&times[index_2]),
+            Keys::None => Ordering::Equal,
+        }
+    }
+}
+
+/// Names of the entries, with their collation keys computed once per entry
+/// for Unicode collation.
+struct Names<'a> {
+    file_names: Vec<&'a OsStr>,
+    collation_keys: Option<Vec<CollationKey>>,
+}
+
+impl<'a> Names<'a> {
+    fn new<E: Entry>(entries: &'a [E], collation: Collation) -> Self {
+        let file_names = entries
+            .iter()
+            .map(|e| file_name(e.path()))
+            .collect::<Vec<_>>();
+        let collation_keys = match collation {
+            Collation::Ascii => None,
+            Collation::Unicode => Some(file_names.iter().map(|n| CollationKey::new(n)).collect()),
+        };
+        Names {
+            file_names,
+            collation_keys,
+        }
+    }
+
+    fn compare(&self, index_1: usize, index_2: usize) -> Ordering {
+        let (file_name_1, file_name_2) = (self.file_names[index_1], self.file_names[index_2]);
+        match self.collation_keys {
+            Some(ref keys) => keys[index_1]
+                .cmp(&keys[index_2])
+                .then_with(|| compare_file_names(file_name_1, file_name_2)),
+            None => compare_file_names(file_name_1, file_name_2),
+        }
+    }
+
+    fn compare_versions(&self, index_1: usize, index_2: usize) -> Ordering {
+        let (file_name_1, file_name_2) = (self.file_names[index_1], self.file_names[index_2]);
+        match self.collation_keys {
+            Some(ref keys) => keys[index_1]
+                .compare_versions(&keys[index_2])
+                .then_with(|| compare_file_names(file_name_1, file_name_2)),
+            None => compare_versions(file_name_1, file_name_2),
+        }
+    }
+}
+
+/// Returns the entries with the children of each directory sorted by the
+/// given key, and by name in case of a tie. Directories and files are then
+/// grouped together, regardless of `reverse`. Entries are expected in
+/// depth-first order, root first, and are returned in that order too.
+pub fn sort_entries<E: Entry>(
+    entries: Vec<E>,
+    sort_key: SortKey,
+    reverse: bool,
+    grouping: Grouping,
+    collation: Collation,
+) -> Vec<E> {
+    if entries.is_empty() || sort_key == SortKey::None && !reverse && grouping == Grouping::None {
+        return entries;
+    }
+    let keys = Keys::new(&entries, sort_key);
+    let names = Names::new(&entries, collation);
+    let compare = |index_1: usize, index_2: usize| match sort_key {
+        SortKey::None => Ordering::Equal,
+        SortKey::Name => names.compare(index_1, index_2),
+        SortKey::Version => names.compare_versions(index_1, index_2),
+        SortKey::Extension => compare_extensions(entries[index_1].path(), entries[index_2].path())
+            .then_with(|| names.compare(index_1, index_2)),
+        SortKey::Size | SortKey::Mtime | SortKey::Ctime => keys
+            .compare(index_1, index_2)
+            .then_with(|| names.compare(index_1, index_2)),
+    };
+
+    // Collect the children of each entry.
+    let mut children = vec![Vec::new(); entries.len()];
+    let mut ancestors = vec![0];
+    for (index, entry) in entries.iter().enumerate().skip(1) {
+        while let Some(&parent_index) = ancestors.last() {
+            if entry.path().starts_with(entries[parent_index].path()) {
+                break;
+            }
+            ancestors.pop();
+        }
+        children[*ancestors.last().unwrap()].push(index);
+        ancestors.push(index);
+    }
+    for child_indices in &mut children {
+        child_indices.sort_by(|index_1, index_2| compare(*index_1, *index_2));
+        if reverse {
+            child_indices.reverse();
+        }
+        match grouping {
+            Grouping::None => {}
+            Grouping::DirsFirst => child_indices.sort_by_key(|index| !entries[*index].is_dir()),
+            Grouping::FilesFirst => child_indices.sort_by_key(|index| entries[*index].is_dir()),
+        }
+    }
+
+    // Compute the new position of each entry.
+    let mut order = Vec::with_capacity(entries.len());
+    let mut stack = vec![0];
+    while let Some(index) = stack.pop() {
+        order.push(index);
+        stack.extend(children[index].iter().rev());
+    }
+    let mut entries = entries.into_iter().map(Some).collect::<Vec<_>>();
+    order
+        .into_iter()
+        .map(|index| entries[index].take().unwrap())
+        .collect()
+}
@@ -0,0 +1,135 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Largest files, largest directories and deepest paths of the printed trees.
+pub struct Top {
+    num_entries: usize,
+    largest_files: Vec<(PathBuf, u64)>,
+    largest_dirs: Vec<(PathBuf, u64)>,
+    deepest_paths: Vec<(PathBuf, usize)>,
+}
+
+/// Sorts `items` by decreasing value then path, and keeps the first `num`.
+fn keep_top<T: Ord + Copy>(items: &mut Vec<(PathBuf, T)>, num: usize) {
+    items.sort_by(|(path_1, value_1), (path_2, value_2)| {
+        value_2.cmp(value_1).then_with(|| path_1.cmp(path_2))
+    });
+    items.truncate(num);
+}
+
+impl Top {
+    pub fn new(num_entries: usize) -> Self {
+        Top {
+            num_entries,
+            largest_files: vec![],
+            largest_dirs: vec![],
+            deepest_paths: vec![],
+        }
+    }
+
+    /// Adds the entries of a tree, given as `(path, is_dir, file_size)`
+    /// tuples starting with the root. The size of a directory is the sum of
+    /// the sizes of the files listed below it.
+    pub fn add_tree<'a, I>(&mut self, entries: I)
+    where
+        I: IntoIterator<Item = (&'a Path, bool, Option<u64>)>,
+    {
+        let mut entries = entries.into_iter().peekable();
+        let root_path = match entries.peek() {
+            Some((root_path, _, _)) => *root_path,
+            None => return,
+        };
+        let mut files = vec![];
+        let mut dir_sizes = HashMap::new();
+        for (path, is_dir, file_size) in entries {
+            let depth = path
+                .strip_prefix(root_path)
+                .map_or(0, |path| path.components().count());
+            self.deepest_paths.push((path.to_path_buf(), depth));
+            if is_dir {
+                dir_sizes.insert(path, 0);
+            } else if let Some(file_size) = file_size {
+                files.push((path, file_size));
+            }
+        }
+        for &(path, file_size) in &files {
+            for ancestor in path.ancestors().skip(1) {
+                if let Some(dir_size) = dir_sizes.get_mut(ancestor) {
+                    *dir_size += file_size;
+                }
+            }
+        }
+        self.largest_files.extend(
+            files
+                .into_iter()
+                .map(|(path, file_size)| (path.to_path_buf(), file_size)),
+        );
+        self.largest_dirs.extend(
+            dir_sizes
+                .into_iter()
+                .filter(|(path, _)| *path != root_path)
+                .map(|(path, dir_size)| (path.to_path_buf(), dir_size)),
+        );
+        keep_top(&mut self.largest_files, self.num_entries);
+        keep_top(&mut self.largest_dirs, self.num_entries);
+        keep_top(&mut self.deepest_paths, self.num_entries);
+    }
+}
+
+fn write_section<T: fmt::Display>(
+    f: &mut fmt::Formatter,
+    title: &str,
+    items: &[(PathBuf, T)],
+) -> fmt::Result {
+    write!(f, "{}:", title)?;
+    let values = items
+        .iter()
+        .map(|(_, value)| value.to_string())
+        .collect::<Vec<_>>();
+    let width = values.iter().map(String::len).max().unwrap_or_default();
+    for ((path, _), value) in items.iter().zip(values) {
+        write!(f, "\n  {:>width$}  {}", value, path.to_string_lossy())?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for Top {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_section(f, "Largest files (bytes)", &self.largest_files)?;
+        writeln!(f)?;
+        write_section(f, "Largest directories (bytes)", &self.largest_dirs)?;
+        writeln!(f)?;
+        write_section(f, "Deepest paths (depth)", &self.deepest_paths)
+    }
+}
+
+#[test]
+fn test_top() {
+    let entries = [
+        ("root", true, None),
+        ("root/a", true, None),
+        ("root/a/big", false, Some(10)),
+        ("root/a/b", true, None),
+        ("root/a/b/small", false, Some(1)),
+        ("root/c", false, Some(5)),
+    ];
+    let mut top = Top::new(2);
+    top.add_tree(
+        entries
+            .iter()
+            .map(|(path, is_dir, file_size)| (Path::new(path), *is_dir, *file_size)),
+    );
+    assert_eq!(
+        top.largest_files,
+        [("root/a/big".into(), 10), ("root/c".into(), 5)]
+    );
+    assert_eq!(
+        top.largest_dirs,
+        [("root/a".into(), 11), ("root/a/b".into(), 1)]
+    );
+    assert_eq!(
+        top.deepest_paths,
+        [("root/a/b/small".into(), 3), ("root/a/b".into(), 2)]
+    );
+}
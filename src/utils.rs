@@ -4,6 +4,8 @@ use std::ffi::OsStr;
 use std::os::unix::ffi::OsStrExt;
 
 use lscolors::LsColors;
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
 
 pub fn get_ls_colors() -> LsColors {
     env::var("GITREE_COLORS")
@@ -53,6 +55,111 @@ pub fn compare_file_names(file_name_1: &OsStr, file_name_2: &OsStr) -> Ordering
     }
 }
 
+fn strip_dot(bytes: &[u8]) -> &[u8] {
+    bytes.strip_prefix(b".").unwrap_or(bytes)
+}
+
+fn take_digits(bytes: &[u8]) -> (&[u8], &[u8]) {
+    let end = bytes
+        .iter()
+        .position(|byte| !byte.is_ascii_digit())
+        .unwrap_or(bytes.len());
+    bytes.split_at(end)
+}
+
+/// Compares names like `compare_versions`, without the final tie-break.
+fn compare_version_bytes(mut bytes_1: &[u8], mut bytes_2: &[u8]) -> Ordering {
+    loop {
+        match (bytes_1.first(), bytes_2.first()) {
+            (None, None) => {
+                return Ordering::Equal;
+            }
+            (None, Some(_)) => {
+                return Ordering::Less;
+            }
+            (Some(_), None) => {
+                return Ordering::Greater;
+            }
+            (Some(byte_1), Some(byte_2)) => {
+                if byte_1.is_ascii_digit() && byte_2.is_ascii_digit() {
+                    let (digits_1, rest_1) = take_digits(bytes_1);
+                    let (digits_2, rest_2) = take_digits(bytes_2);
+                    let digits_1 = &digits_1[digits_1.iter().take_while(|b| **b == b'0').count()..];
+                    let digits_2 = &digits_2[digits_2.iter().take_while(|b| **b == b'0').count()..];
+                    match digits_1
+                        .len()
+                        .cmp(&digits_2.len())
+                        .then(digits_1.cmp(digits_2))
+                    {
+                        Ordering::Equal => {
+                            bytes_1 = rest_1;
+                            bytes_2 = rest_2;
+                        }
+                        ordering => return ordering,
+                    }
+                } else {
+                    match byte_1
+                        .to_ascii_lowercase()
+                        .cmp(&byte_2.to_ascii_lowercase())
+                    {
+                        Ordering::Equal => {
+                            bytes_1 = &bytes_1[1..];
+                            bytes_2 = &bytes_2[1..];
+                        }
+                        ordering => return ordering,
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Compares file names like `compare_file_names`, except that sequences of
+/// digits are compared by their numeric value.
+pub fn compare_versions(file_name_1: &OsStr, file_name_2: &OsStr) -> Ordering {
+    compare_version_bytes(
+        strip_dot(file_name_1.as_bytes()),
+        strip_dot(file_name_2.as_bytes()),
+    )
+    .then_with(|| compare_file_names(file_name_1, file_name_2))
+}
+
+/// Collation keys of a file name: its case folded compatibility
+/// decomposition, without and then with combining marks. Keys compare file
+/// names by their Unicode characters, ignoring case, accents and
+/// compatibility variants first, then taking accents into account.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct CollationKey {
+    primary: String,
+    secondary: String,
+}
+
+impl CollationKey {
+    pub fn new(file_name: &OsStr) -> Self {
+        let file_name = file_name.to_string_lossy();
+        let file_name = file_name.strip_prefix('.').unwrap_or(&file_name);
+        // Lowercasing the uppercase form folds characters such as `ß` or
+        // final sigma the way full case folding does.
+        let secondary = file_name
+            .nfkd()
+            .flat_map(char::to_uppercase)
+            .flat_map(char::to_lowercase)
+            .collect::<String>();
+        let primary = secondary
+            .chars()
+            .filter(|c| !is_combining_mark(*c))
+            .collect();
+        CollationKey { primary, secondary }
+    }
+
+    /// Compares keys like `compare_versions` compares file names.
+    pub fn compare_versions(&self, other: &Self) -> Ordering {
+        compare_version_bytes(self.primary.as_bytes(), other.primary.as_bytes()).then_with(|| {
+            compare_version_bytes(self.secondary.as_bytes(), other.secondary.as_bytes())
+        })
+    }
+}
+
 #[test]
 fn test_compare_file_names() {
     fn compare_str(file_name_1: &str, file_name_2: &str) -> Ordering {
@@ -72,3 +179,63 @@ fn test_compare_file_names() {
     // Size matters.
     assert_eq!(compare_str("foo", "foobar"), Ordering::Less);
 }
+
+#[test]
+fn test_compare_versions() {
+    fn compare_str(file_name_1: &str, file_name_2: &str) -> Ordering {
+        compare_versions(OsStr::new(file_name_1), OsStr::new(file_name_2))
+    }
+
+    // Numeric order.
+    assert_eq!(compare_str("file2", "file10"), Ordering::Less);
+    assert_eq!(compare_str("v1.10.0", "v1.9.3"), Ordering::Greater);
+
+    // Leading zeros.
+    assert_eq!(compare_str("file002", "file10"), Ordering::Less);
+
+    // Ignore case.
+    assert_eq!(compare_str("File2", "file10"), Ordering::Less);
+
+    // Size matters.
+    assert_eq!(compare_str("file", "file1"), Ordering::Less);
+}
+
+#[test]
+fn test_collation_keys() {
+    fn compare_str(file_name_1: &str, file_name_2: &str) -> Ordering {
+        let (file_name_1, file_name_2) = (OsStr::new(file_name_1), OsStr::new(file_name_2));
+        CollationKey::new(file_name_1)
+            .cmp(&CollationKey::new(file_name_2))
+            .then_with(|| compare_file_names(file_name_1, file_name_2))
+    }
+
+    // Accents come second.
+    assert_eq!(compare_str("éclair", "fig"), Ordering::Less);
+    assert_eq!(compare_str("zebre", "zèbre"), Ordering::Less);
+    assert_eq!(compare_str("Zèbre", "zebu"), Ordering::Less);
+
+    // Composed and decomposed forms.
+    assert_eq!(compare_str("e\u{301}a", "\u{e9}b"), Ordering::Less);
+
+    // Full-width characters.
+    assert_eq!(compare_str("\u{ff21}pple", "banana"), Ordering::Less);
+
+    // Ignore case and leading dot.
+    assert_eq!(compare_str(".Éla", "élan"), Ordering::Less);
+    assert_eq!(
+        compare_str("ÉLAN", "élan"),
+        compare_str("élan", "ÉLAN").reverse()
+    );
+
+    // Full case folding.
+    assert_eq!(
+        CollationKey::new(OsStr::new("Straße")),
+        CollationKey::new(OsStr::new("STRASSE"))
+    );
+    assert_eq!(compare_str("straße", "strast"), Ordering::Less);
+
+    // Version order.
+    let key = |file_name| CollationKey::new(OsStr::new(file_name));
+    assert_eq!(key("é10").compare_versions(&key("É9")), Ordering::Greater);
+    assert_eq!(key("e9").compare_versions(&key("é9")), Ordering::Less);
+}